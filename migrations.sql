@@ -0,0 +1,66 @@
+-- Changes to the database schema, oldest first.
+-- Before deploying a new version, run the statements added since the last deploy.
+
+-- Update modes for data sources (APPEND, SYNC)
+ALTER TABLE `data_source`
+    ADD `update_mode` VARCHAR(16) NOT NULL DEFAULT 'APPEND',
+    ADD `key_column` INT UNSIGNED NULL DEFAULT NULL;
+
+-- Key hashes for unique key checks, see `HeaderSchema::get_key_md5`
+ALTER TABLE `row`
+    ADD `key_md5` CHAR(32) NULL DEFAULT NULL,
+    ADD INDEX `list_key_md5` (`list_id`, `key_md5`);
+
+-- CSV, TSV and Excel sources can have a header row
+ALTER TABLE `data_source`
+    ADD `first_row_is_header` TINYINT(1) NOT NULL DEFAULT 0;
+
+-- Worksheet and cell range for Excel sources
+ALTER TABLE `data_source`
+    ADD `excel_sheet` VARCHAR(255) NULL DEFAULT NULL,
+    ADD `excel_range` VARCHAR(64) NULL DEFAULT NULL;
+
+-- JSON Pointer to the rows in JSON sources
+ALTER TABLE `data_source`
+    ADD `json_pointer` VARCHAR(255) NULL DEFAULT NULL;
+
+-- Background jobs that update a list from a data source
+CREATE TABLE `job` (
+    `id` INT UNSIGNED NOT NULL AUTO_INCREMENT,
+    `source_id` INT UNSIGNED NOT NULL,
+    `list_id` INT UNSIGNED NOT NULL,
+    `user_id` INT UNSIGNED NOT NULL,
+    `scheduled` TINYINT(1) NOT NULL DEFAULT 0,
+    `state` VARCHAR(16) NOT NULL DEFAULT 'QUEUED',
+    `rows_processed` INT UNSIGNED NOT NULL DEFAULT 0,
+    `stats` TEXT NULL DEFAULT NULL,
+    `error` TEXT NULL DEFAULT NULL,
+    `created` DATETIME NOT NULL,
+    `modified` DATETIME NOT NULL,
+    PRIMARY KEY (`id`),
+    KEY `state` (`state`),
+    KEY `source_state` (`source_id`, `state`),
+    KEY `list_state` (`list_id`, `state`)
+);
+
+-- Scheduled refreshes, see `RefreshSchedule`
+ALTER TABLE `data_source`
+    ADD `refresh_schedule` VARCHAR(255) NULL DEFAULT NULL,
+    ADD `auto_snapshot` TINYINT(1) NOT NULL DEFAULT 0;
+
+-- The last download of each URL source, for conditional requests
+CREATE TABLE `data_source_cache` (
+    `source_id` INT UNSIGNED NOT NULL,
+    `url` TEXT NOT NULL,
+    `path` VARCHAR(255) NOT NULL,
+    `etag` VARCHAR(255) NULL DEFAULT NULL,
+    `last_modified` VARCHAR(64) NULL DEFAULT NULL,
+    `content_md5` CHAR(32) NOT NULL,
+    `imported_md5` CHAR(32) NULL DEFAULT NULL,
+    `checked` DATETIME NOT NULL,
+    PRIMARY KEY (`source_id`)
+);
+
+-- File to read from a zip archive source
+ALTER TABLE `data_source`
+    ADD `archive_member` VARCHAR(255) NULL DEFAULT NULL;
@@ -1,13 +1,23 @@
 use crate::app_state::AppState;
-use crate::data_source::{DataSource, DataSourceFormat, DataSourceType};
+use crate::data_source::{
+    DataSource, DataSourceFormat, DataSourceType, DataSourceUpdateMode, SheetRange,
+};
+use crate::export_ods::rows_as_ods;
+use crate::export_stream::{rows_as_text, stream_rows};
+use crate::export_xlsx::rows_as_xlsx;
 use crate::file::File;
 use crate::gulp_response::ContentType;
 use crate::header::{DbId, HeaderSchema};
+use crate::job::Job;
 use crate::list::List;
 use crate::oauth::*;
+use crate::row_filter::RowFilter;
+use crate::row_sort::RowSort;
+use crate::scheduler::RefreshSchedule;
 use crate::user::User;
 use crate::GulpError;
 use axum::{
+    body::StreamBody,
     extract::{DefaultBodyLimit, Multipart, Path, Query, State, TypedHeader},
     http::StatusCode,
     response::{IntoResponse, Response},
@@ -28,6 +38,7 @@ use tracing_subscriber;
 
 const MAX_UPLOAD_MB: usize = 50;
 const EMPTY_HEADER_SCHEMA_ID: DbId = 3;
+const SOURCE_JOBS_LIMIT: usize = 50;
 
 async fn auth_info(
     State(state): State<Arc<AppState>>,
@@ -99,10 +110,10 @@ async fn list_sources(State(state): State<Arc<AppState>>, Path(id): Path<DbId>)
 async fn source_header(
     State(state): State<Arc<AppState>>,
     Path(source_id): Path<DbId>,
-    Query(_params): Query<HashMap<String, String>>,
+    Query(params): Query<HashMap<String, String>>,
 ) -> Response {
     // TODO params with header
-    let source = match DataSource::from_db(&state, source_id).await {
+    let mut source = match DataSource::from_db(&state, source_id).await {
         Some(source) => source,
         None => {
             return json_error_gone(&format!(
@@ -110,7 +121,24 @@ async fn source_header(
             ))
         }
     };
-    let cell_set_result = source.guess_headers(Some(50)).await;
+    // Preview another worksheet or range, without changing the source
+    if let Some(sheet) = params.get("excel_sheet") {
+        source.excel_sheet = Some(sheet.to_owned());
+    }
+    if let Some(range) = params.get("excel_range") {
+        source.excel_range = Some(range.to_owned());
+    }
+    if let Some(pointer) = params.get("json_pointer") {
+        source.json_pointer = Some(pointer.to_owned());
+    }
+    if let Some(member) = params.get("archive_member") {
+        source.archive_member = Some(member.to_owned());
+    }
+    let sheets = match source.get_sheet_names(&state).await {
+        Ok(sheets) => sheets,
+        Err(e) => return json_error(&e.to_string()),
+    };
+    let cell_set_result = source.guess_headers(&state, Some(50)).await;
     let cell_set = match cell_set_result {
         Ok(cell_set) => cell_set,
         Err(e) => return json_error(&e.to_string()),
@@ -123,7 +151,7 @@ async fn source_header(
         .iter()
         .map(|row| row.as_json(&header))
         .collect();
-    let j = json!({"status":"OK","headers":header,"rows":rows});
+    let j = json!({"status":"OK","headers":header,"rows":rows,"sheets":sheets});
     (StatusCode::OK, Json(j)).into_response()
 }
 
@@ -141,32 +169,104 @@ async fn source_update(
             ))
         }
     };
-    let list = match AppState::get_list(&state, source.list_id).await {
-        Some(list) => list,
+    let user = match User::from_cookies(&state, &cookies, &params).await {
+        Some(user) => user,
+        None => return json_error("Not logged in"),
+    };
+    if !user.can_update_from_source(source.list_id).await {
+        return json_error(&format!(
+            "You are nor allowed to update list {}. Please ask the list admin(s) for permission.",
+            source.list_id
+        ));
+    }
+    // The update runs in the background; progress is available via /job/:job_id
+    let job = match Job::create(&state, &source, user.id, false).await {
+        Ok(job) => job,
+        Err(e) => return json_error(&format!("Error starting update from source: {e}")),
+    };
+    let j = json!({"status":"OK","job":job});
+    (StatusCode::OK, Json(j)).into_response()
+}
+
+/// Sets or removes (empty `refresh_schedule`) the automatic refresh of a data source
+async fn source_schedule(
+    State(state): State<Arc<AppState>>,
+    Path(source_id): Path<DbId>,
+    Query(params): Query<HashMap<String, String>>,
+    cookies: Option<TypedHeader<headers::Cookie>>,
+) -> Response {
+    let mut source = match DataSource::from_db(&state, source_id).await {
+        Some(source) => source,
         None => {
             return json_error_gone(&format!(
-                "Error retrieving list; No list #{} perhaps?",
-                source.list_id
+                "Error retrieving source; No source #{source_id} perhaps?"
             ))
         }
     };
-    let list = list.lock().await;
     let user = match User::from_cookies(&state, &cookies, &params).await {
         Some(user) => user,
         None => return json_error("Not logged in"),
     };
-    if !user.can_update_from_source(list.id).await {
+    if !user.can_update_from_source(source.list_id).await {
         return json_error(&format!(
             "You are nor allowed to update list {}. Please ask the list admin(s) for permission.",
-            list.id
+            source.list_id
         ));
     }
-    let x = list.update_from_source(&source, user.id).await;
-    match x {
-        Ok(_) => {}
-        Err(e) => return json_error(&format!("Error updating from source: {e}")),
+    let (refresh_schedule, auto_snapshot) = match parse_refresh_schedule(&params) {
+        Ok(x) => x,
+        Err(e) => return json_error(&e),
+    };
+    if refresh_schedule.is_some() && !source.can_be_scheduled() {
+        return json_error("Only URL, PAGEPILE and SPARQL sources can be refreshed automatically");
     }
-    let j = json!({"status":"OK"});
+    if let Err(e) = source
+        .set_refresh_schedule(&state, refresh_schedule, auto_snapshot)
+        .await
+    {
+        return json_error(&format!("Could not set refresh schedule: {e}"));
+    }
+    let j = json!({"status":"OK","data":source});
+    (StatusCode::OK, Json(j)).into_response()
+}
+
+/// The recent update jobs of a data source, scheduled or not, with their outcome
+async fn source_jobs(State(state): State<Arc<AppState>>, Path(source_id): Path<DbId>) -> Response {
+    let jobs = match Job::for_source(&state, source_id, SOURCE_JOBS_LIMIT).await {
+        Ok(jobs) => jobs,
+        Err(e) => return json_error(&e.to_string()),
+    };
+    let j = json!({"status":"OK","jobs":jobs});
+    (StatusCode::OK, Json(j)).into_response()
+}
+
+/// Reads the `refresh_schedule` and `auto_snapshot` parameters
+fn parse_refresh_schedule(
+    params: &HashMap<String, String>,
+) -> Result<(Option<String>, bool), String> {
+    let refresh_schedule = params
+        .get("refresh_schedule")
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+    if let Some(schedule) = &refresh_schedule {
+        if RefreshSchedule::new(schedule).is_none() {
+            return Err(format!(
+                "Invalid refresh_schedule '{schedule}'; sources can be refreshed at most every 15 minutes"
+            ));
+        }
+    }
+    let auto_snapshot = params.get("auto_snapshot").map(|s| s.as_str()) == Some("1");
+    Ok((refresh_schedule, auto_snapshot))
+}
+
+async fn job_info(State(state): State<Arc<AppState>>, Path(job_id): Path<DbId>) -> Response {
+    let job = match Job::from_db(&state, job_id).await {
+        Some(job) => job,
+        None => {
+            return json_error_gone(&format!("Error retrieving job; No job #{job_id} perhaps?"))
+        }
+    };
+    let j = json!({"status":"OK","job":job});
     (StatusCode::OK, Json(j)).into_response()
 }
 
@@ -195,8 +295,68 @@ async fn source_create(
             Some(ds_format) => ds_format,
             None => return json_error("Invalid format"),
         },
+        None if matches!(ds_type, DataSourceType::SPARQL) => DataSourceFormat::SPARQLJSON,
         None => return json_error("Missing format"),
     };
+    if matches!(ds_type, DataSourceType::SPARQL)
+        != matches!(ds_format, DataSourceFormat::SPARQLJSON)
+    {
+        return json_error("SPARQL sources, and only those, use the SPARQL_JSON format");
+    }
+    let update_mode = match params
+        .get("update_mode")
+        .map(|s| DataSourceUpdateMode::new(s))
+    {
+        Some(update_mode) => match update_mode {
+            Some(update_mode) => update_mode,
+            None => return json_error("Invalid update_mode"),
+        },
+        None => DataSourceUpdateMode::APPEND,
+    };
+    let key_column = match params.get("key_column").map(|s| s.parse::<usize>()) {
+        Some(Ok(key_column)) => Some(key_column),
+        Some(Err(_)) => return json_error("Invalid key_column"),
+        None => None,
+    };
+    if update_mode == DataSourceUpdateMode::SYNC && key_column.is_none() {
+        // Without a key column, a sync uses the key columns of the list
+        let list = match AppState::get_list(&state, list_id).await {
+            Some(list) => list,
+            None => {
+                return json_error_gone(&format!(
+                    "Error retrieving list; No list #{list_id} perhaps?"
+                ))
+            }
+        };
+        if list.lock().await.header.schema.key_columns.is_empty() {
+            return json_error(
+                "update_mode SYNC requires a key_column, or key columns in the list header",
+            );
+        }
+    }
+    let first_row_is_header = params.get("first_row_is_header").map(|s| s.as_str()) == Some("1");
+    let excel_sheet = params.get("excel_sheet").cloned().filter(|s| !s.is_empty());
+    let excel_range = params.get("excel_range").cloned().filter(|s| !s.is_empty());
+    if let Some(range) = &excel_range {
+        if SheetRange::new(range).is_none() {
+            return json_error("Invalid excel_range");
+        }
+    }
+    let json_pointer = params
+        .get("json_pointer")
+        .cloned()
+        .filter(|s| !s.is_empty());
+    if json_pointer.as_ref().is_some_and(|p| !p.starts_with('/')) {
+        return json_error("Invalid json_pointer; it needs to start with '/'");
+    }
+    let (refresh_schedule, auto_snapshot) = match parse_refresh_schedule(&params) {
+        Ok(x) => x,
+        Err(e) => return json_error(&e),
+    };
+    let archive_member = params
+        .get("archive_member")
+        .cloned()
+        .filter(|s| !s.is_empty());
 
     let mut location = match params.get("location") {
         Some(location) => location.to_owned(),
@@ -221,7 +381,19 @@ async fn source_create(
         source_format: ds_format,
         location,
         user_id: user.id,
+        update_mode,
+        key_column,
+        first_row_is_header,
+        excel_sheet,
+        excel_range,
+        json_pointer,
+        refresh_schedule,
+        auto_snapshot,
+        archive_member,
     };
+    if ds.refresh_schedule.is_some() && !ds.can_be_scheduled() {
+        return json_error("Only URL, PAGEPILE and SPARQL sources can be refreshed automatically");
+    }
     if ds.create(&state).await.is_none() {
         return json_error("Could not create data source");
     }
@@ -247,6 +419,9 @@ async fn list_snapshot(
     if !user.can_create_snapshot(list.id).await {
         return json_error("You are nor allowed to create a new snapshot for this list. Please ask the list admin(s) for permission.");
     }
+    if let Some(response) = json_error_if_job_running(&state, list.id).await {
+        return response;
+    }
     let old_revision_id = list.revision_id;
     let new_revision_id = match list.snapshot().await {
         Ok(rev_id) => rev_id,
@@ -264,6 +439,39 @@ async fn list_snapshot(
     (StatusCode::OK, Json(j)).into_response()
 }
 
+async fn list_revert(
+    State(state): State<Arc<AppState>>,
+    Path((list_id, revision_id)): Path<(DbId, DbId)>,
+    Query(params): Query<HashMap<String, String>>,
+    cookies: Option<TypedHeader<headers::Cookie>>,
+) -> Response {
+    let user = match User::from_cookies(&state, &cookies, &params).await {
+        Some(user) => user,
+        None => return json_error("Not logged in"),
+    };
+    if !user.can_revert_list(list_id).await {
+        return json_error("Only list admins can revert a list to an earlier revision.");
+    }
+    let list = match AppState::get_list(&state, list_id).await {
+        Some(list) => list,
+        None => {
+            return json_error_gone(&format!(
+                "Error retrieving list; No list #{list_id} perhaps?"
+            ))
+        }
+    };
+    let mut list = list.lock().await;
+    if let Some(response) = json_error_if_job_running(&state, list_id).await {
+        return response;
+    }
+    let stats = match list.revert_to_revision(revision_id, user.id).await {
+        Ok(stats) => stats,
+        Err(e) => return json_error(&format!("Error reverting list: {e}")),
+    };
+    let j = json!({"status":"OK","stats":stats,"revision_id":list.revision_id});
+    (StatusCode::OK, Json(j)).into_response()
+}
+
 async fn header_schemas(State(state): State<Arc<AppState>>) -> Response {
     let hs = match state.get_all_header_schemas().await {
         Ok(hs) => hs,
@@ -281,6 +489,20 @@ fn json_error(s: &str) -> Response {
     json_error_code(StatusCode::OK, s)
 }
 
+/// An error if a job is updating the list; call while holding the list lock
+async fn json_error_if_job_running(state: &Arc<AppState>, list_id: DbId) -> Option<Response> {
+    match Job::is_running_for_list(state, list_id).await {
+        Ok(false) => None,
+        Ok(true) => Some(json_error(&format!(
+            "List #{list_id} is being updated from a data source; please try again when the update is done"
+        ))),
+        Err(e) => Some(json_error_code(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &e.to_string(),
+        )),
+    }
+}
+
 fn json_error_gone(s: &str) -> Response {
     json_error_code(StatusCode::GONE, s)
 }
@@ -345,6 +567,9 @@ async fn list_row(
         }
     };
     let list = list.lock().await;
+    if let Some(response) = json_error_if_job_running(&state, list_id).await {
+        return response;
+    }
 
     let row_json = match params.get("json") {
         Some(s) => s,
@@ -383,6 +608,69 @@ async fn list_row(
     (StatusCode::OK, Json(j)).into_response()
 }
 
+async fn list_row_history(
+    State(state): State<Arc<AppState>>,
+    Path((list_id, row_num)): Path<(DbId, DbId)>,
+) -> Response {
+    let list = match AppState::get_list(&state, list_id).await {
+        Some(list) => list,
+        None => {
+            return json_error_gone(&format!(
+                "Error retrieving list; No list #{list_id} perhaps?"
+            ))
+        }
+    };
+    let list = list.lock().await;
+    let history = match list.get_row_history(row_num).await {
+        Ok(history) => history,
+        Err(e) => return json_error(&e.to_string()),
+    };
+    let user_ids: Vec<DbId> = history.iter().filter_map(|v| v["user"].as_u64()).collect();
+    let users = match list.get_users_by_id(&user_ids).await {
+        Ok(users) => users,
+        Err(e) => return json_error(&format!("Error retrieving user details: {e}")),
+    };
+    let j = json!({"status":"OK","history":history,"users":users});
+    (StatusCode::OK, Json(j)).into_response()
+}
+
+async fn list_delete_row(
+    State(state): State<Arc<AppState>>,
+    Path((list_id, row_num)): Path<(DbId, DbId)>,
+    Query(params): Query<HashMap<String, String>>,
+    cookies: Option<TypedHeader<headers::Cookie>>,
+) -> Response {
+    let user = match User::from_cookies(&state, &cookies, &params).await {
+        Some(user) => user,
+        None => return json_error("Please log in to delete a row"),
+    };
+    if !user.can_delete_row(list_id).await {
+        return json_error(&format!(
+            "You do not have permission to delete a row in list {list_id}"
+        ));
+    }
+
+    let list = match AppState::get_list(&state, list_id).await {
+        Some(list) => list,
+        None => {
+            return json_error_gone(&format!(
+                "Error retrieving list; No list #{list_id} perhaps?"
+            ))
+        }
+    };
+    let list = list.lock().await;
+    if let Some(response) = json_error_if_job_running(&state, list_id).await {
+        return response;
+    }
+    match list.delete_row(row_num, user.id).await {
+        Ok(_) => {}
+        Err(e) => return json_error(&e.to_string()),
+    }
+
+    let j = json!({"status":"OK"});
+    (StatusCode::OK, Json(j)).into_response()
+}
+
 async fn list_header_schema(
     State(state): State<Arc<AppState>>,
     Path((list_id, header_schema_id)): Path<(DbId, DbId)>,
@@ -411,6 +699,9 @@ async fn list_header_schema(
         Err(e) => return json_error(&e.to_string()),
     };
     let mut list = list.lock().await;
+    if let Some(response) = json_error_if_job_running(&state, list_id).await {
+        return response;
+    }
     match list.set_header_schema(header_schema).await {
         Ok(_) => {}
         Err(e) => return json_error(&e.to_string()),
@@ -450,17 +741,12 @@ async fn new_header_schema(
     }
 }
 
-fn rows_as_xsv(
-    list: &List,
-    rows: &Vec<crate::row::Row>,
-    delimiter: u8,
-) -> Result<String, GulpError> {
-    // TODO header
+fn records_as_xsv(records: &[Vec<String>], delimiter: u8) -> Result<String, GulpError> {
     let mut wtr = WriterBuilder::new()
         .delimiter(delimiter)
         .from_writer(vec![]);
-    for row in rows {
-        wtr.write_record(&row.as_vec(&list.header))?;
+    for record in records {
+        wtr.write_record(record)?;
     }
     let inner = wtr
         .into_inner()
@@ -469,6 +755,66 @@ fn rows_as_xsv(
     Ok(ret)
 }
 
+async fn list_diff(
+    State(state): State<Arc<AppState>>,
+    Path(list_id): Path<DbId>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    let format: String = params.get("format").unwrap_or(&"json".into()).into();
+    let list = match AppState::get_list(&state, list_id).await {
+        Some(list) => list,
+        None => {
+            return json_error_gone(&format!(
+                "Error retrieving list; No list #{list_id} perhaps?"
+            ))
+        }
+    };
+    let list = list.lock().await;
+    let to: DbId = params
+        .get("to")
+        .map(|s| s.parse::<DbId>().unwrap_or(list.revision_id))
+        .unwrap_or(list.revision_id);
+    let from: DbId = params
+        .get("from")
+        .map(|s| s.parse::<DbId>().unwrap_or(to.saturating_sub(1)))
+        .unwrap_or(to.saturating_sub(1));
+    let diff = match list.diff_revisions(from, to).await {
+        Ok(diff) => diff,
+        Err(e) => return json_error(&e.to_string()),
+    };
+    let users = match list.get_users_by_id(&diff.user_ids()).await {
+        Ok(users) => users,
+        Err(e) => return json_error(&format!("Error retrieving user details: {e}")),
+    };
+
+    let format = match ContentType::new(&format) {
+        Some(format) => format,
+        None => return json_error(&format!("Unsupported format: '{format}'")),
+    };
+    let filename = format!(
+        "{}.diff_{from}_{to}.{}",
+        list.get_file_basename(Some(to)),
+        format.file_ending()
+    );
+    match format {
+        ContentType::TSV => {
+            let s = match records_as_xsv(&diff.as_records(&users), b'\t') {
+                Ok(s) => s,
+                Err(e) => return json_error(&e.to_string()),
+            };
+            (format.download_headers(Some(filename)), s).into_response()
+        }
+        ContentType::JSON => {
+            let j = json!({"status":"OK","diff":diff,"users":users});
+            (StatusCode::OK, Json(j)).into_response()
+        }
+        other => json_error(&format!(
+            "ERROR: Output format '{}' is not supported",
+            other.as_str()
+        )),
+    }
+}
+
 async fn list_rows(
     State(state): State<Arc<AppState>>,
     Path(list_id): Path<DbId>,
@@ -482,6 +828,8 @@ async fn list_rows(
     let len: Option<u64> = params
         .get("len")
         .map(|s| s.parse::<u64>().unwrap_or(u64::MAX));
+    // Set `no_header=1` to omit the header line in CSV/TSV/JSONL output
+    let with_header = params.get("no_header").map(|s| s.as_str()) != Some("1");
     let list = match AppState::get_list(&state, list_id).await {
         Some(list) => list,
         None => {
@@ -495,44 +843,94 @@ async fn list_rows(
         .get("revision_id")
         .map(|s| s.parse::<DbId>().unwrap_or(list.revision_id))
         .unwrap_or(list.revision_id);
-    let rows = match list
-        .get_rows_for_revision_paginated(revision_id, start, len)
-        .await
-    {
-        Ok(rows) => rows,
+    let filter = match RowFilter::from_params(&params, &list.header) {
+        Ok(filter) => filter,
+        Err(e) => return json_error(&e.to_string()),
+    };
+    let sort = match RowSort::from_params(&params, &list.header) {
+        Ok(sort) => sort,
         Err(e) => return json_error(&e.to_string()),
     };
-
     let format = match ContentType::new(&format) {
         Some(format) => format,
         None => return json_error(&format!("Unsupported format: '{format}'")),
     };
-
     let filename = format!(
         "{}.{}",
         list.get_file_basename(Some(revision_id)),
         format.file_ending()
     );
+
+    // Text formats are streamed in row order; sorted rows are read with one query
+    let is_text = matches!(
+        format,
+        ContentType::CSV | ContentType::TSV | ContentType::JSONL
+    );
+    if is_text && sort.is_none() {
+        let stream = stream_rows(
+            list.clone(),
+            revision_id,
+            format.clone(),
+            filter,
+            start as usize,
+            len.map(|len| len as usize),
+            with_header,
+        );
+        return (
+            format.download_headers(Some(filename)),
+            StreamBody::new(stream),
+        )
+            .into_response();
+    }
+
+    let (rows, total) = match list
+        .get_filtered_rows_for_revision(revision_id, &filter, &sort, start, len)
+        .await
+    {
+        Ok(ret) => ret,
+        Err(e) => return json_error(&e.to_string()),
+    };
     match format {
-        ContentType::CSV => {
-            let s = match rows_as_xsv(&list, &rows, b',') {
+        ContentType::CSV | ContentType::TSV | ContentType::JSONL => {
+            let s = match rows_as_text(&list, &rows, &format, with_header) {
                 Ok(s) => s,
                 Err(e) => return json_error(&e.to_string()),
             };
             (format.download_headers(Some(filename)), s).into_response()
         }
-        ContentType::TSV => {
-            let s = match rows_as_xsv(&list, &rows, b'\t') {
-                Ok(s) => s,
+        ContentType::XLSX => {
+            let data = match rows_as_xlsx(&list, &rows, revision_id) {
+                Ok(data) => data,
                 Err(e) => return json_error(&e.to_string()),
             };
-            (format.download_headers(Some(filename)), s).into_response()
+            (format.download_headers(Some(filename)), data).into_response()
+        }
+        ContentType::ODS => {
+            let data = match rows_as_ods(&list, &rows, revision_id) {
+                Ok(data) => data,
+                Err(e) => return json_error(&e.to_string()),
+            };
+            (format.download_headers(Some(filename)), data).into_response()
         }
         ContentType::JSON => {
             // default format: json
+            let entities = match params.get("labels") {
+                Some(language) => {
+                    let ids = list.get_wikidata_entity_ids(&rows);
+                    match state.wikidata_labels.get(&ids, language).await {
+                        Ok(entities) => Some(entities),
+                        Err(e) => return json_error(&e.to_string()),
+                    }
+                }
+                None => None,
+            };
             let rows: Vec<serde_json::Value> =
                 rows.iter().map(|row| row.as_json(&list.header)).collect();
-            let j = json!({"status":"OK","rows":rows}); // TODO header
+            let mut j =
+                json!({"status":"OK","header":list.header.schema,"rows":rows,"total":total});
+            if let Some(entities) = entities {
+                j["entities"] = json!(entities);
+            }
             (format.download_headers(Some(filename)), Json(j)).into_response()
         }
         other => json_error(&format!(
@@ -599,6 +997,9 @@ pub async fn run_server(shared_state: Arc<AppState>) -> Result<(), GulpError> {
 
     let cors = CorsLayer::new().allow_origin(Any);
 
+    tokio::spawn(crate::job::run_worker(shared_state.clone()));
+    tokio::spawn(crate::scheduler::run_scheduler(shared_state.clone()));
+
     let app = Router::new()
         .route("/auth/login", get(toolforge_auth))
         .route("/auth/authorized", get(login_authorized))
@@ -607,7 +1008,9 @@ pub async fn run_server(shared_state: Arc<AppState>) -> Result<(), GulpError> {
         .route("/auth/lists/:rights", get(my_lists))
         .route("/list/rows/:id", get(list_rows))
         .route("/list/info/:id", get(list_info))
+        .route("/list/diff/:id", get(list_diff))
         .route("/list/snapshot/:id", get(list_snapshot))
+        .route("/list/revert/:list_id/:revision_id", get(list_revert))
         .route("/list/sources/:id", get(list_sources))
         .route("/list/new", get(new_list))
         .route(
@@ -615,11 +1018,16 @@ pub async fn run_server(shared_state: Arc<AppState>) -> Result<(), GulpError> {
             get(list_header_schema),
         )
         .route("/list/row/:list_id/:row_num", get(list_row))
+        .route("/list/delete_row/:list_id/:row_num", get(list_delete_row))
+        .route("/list/row_history/:list_id/:row_num", get(list_row_history))
         .route("/header/schemas", get(header_schemas))
         .route("/header/schema/new", get(new_header_schema))
         .route("/source/update/:source_id", get(source_update))
         .route("/source/header/:source_id", get(source_header))
         .route("/source/create/:list_id", get(source_create))
+        .route("/source/schedule/:source_id", get(source_schedule))
+        .route("/source/jobs/:source_id", get(source_jobs))
+        .route("/job/:job_id", get(job_info))
         .route("/upload", post(upload))
         .nest_service("/", ServeDir::new("html"))
         .with_state(shared_state.clone())
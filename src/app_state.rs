@@ -1,4 +1,6 @@
 use crate::database_session_store::DatabaseSessionStore;
+use crate::fetcher::{Fetcher, FetcherConfig};
+use crate::wikidata::{WikidataLabels, WIKIDATA_API};
 use crate::GulpError;
 use crate::{header::DbId, list::List};
 use mysql_async::{prelude::*, Conn, Opts, OptsBuilder, PoolConstraints, PoolOpts};
@@ -10,7 +12,7 @@ use std::fs::File;
 use std::sync::Arc;
 use std::time::Duration;
 use std::{collections::HashMap, env};
-use tokio::sync::{Mutex, RwLock};
+use tokio::sync::{Mutex, Notify, RwLock};
 
 type ListMutex = Arc<Mutex<List>>;
 
@@ -25,7 +27,10 @@ pub struct AppState {
     pub store: DatabaseSessionStore,
     pub oauth_client: BasicClient,
     pub webserver_port: u16,
+    pub wikidata_labels: WikidataLabels,
     pub fixed_user_id: Option<DbId>, // for local testing only
+    pub job_notify: Arc<Notify>,     // wakes up the job worker
+    pub fetcher: Fetcher,            // for user-supplied URLs
 }
 
 impl AppState {
@@ -68,7 +73,13 @@ impl AppState {
             }, //MemoryStore::new(),//
             oauth_client,
             webserver_port: config["webserver"]["port"].as_u64().unwrap_or(8000) as u16,
+            wikidata_labels: WikidataLabels::new(
+                config["wikidata_api"].as_str().unwrap_or(WIKIDATA_API),
+            ),
             fixed_user_id: config["fixed_user_id"].as_u64(), // for local testing only
+            job_notify: Arc::new(Notify::new()),
+            fetcher: Fetcher::new(FetcherConfig::from_config(&config["fetcher"]))
+                .expect("Can not build fetcher"),
         };
         ret
     }
@@ -220,6 +231,14 @@ impl AppState {
         pathbuf.set_extension("gulp");
         pathbuf.to_string_lossy().to_string()
     }
+
+    /// The file that keeps the last download of a data source
+    pub fn get_source_cache_filename(&self, source_id: DbId) -> String {
+        let mut pathbuf = std::path::PathBuf::from(&self.import_file_path);
+        pathbuf.push(format!("source_{source_id}"));
+        pathbuf.set_extension("cache");
+        pathbuf.to_string_lossy().to_string()
+    }
 }
 
 #[cfg(test)]
@@ -18,6 +18,49 @@ impl WikiPage {
         }
     }
 
+    /// Link to the page on its wiki. None if wiki or namespace are unknown.
+    pub fn url(&self, column: &HeaderColumn) -> Option<String> {
+        let wiki = self.wiki.as_ref().or(column.wiki.as_ref())?;
+        let namespace_id = self.namespace_id.or(column.namespace_id).unwrap_or(0);
+        let prefix = Self::canonical_namespace_prefix(namespace_id)?;
+        let server = crate::app_state::AppState::get_server_for_wiki(wiki);
+        let title = format!("{prefix}{}", self.title).replace(' ', "_");
+        Some(format!(
+            "https://{server}/wiki/{}",
+            Self::encode_title(&title)
+        ))
+    }
+
+    /// Canonical namespace names work on every wiki
+    fn canonical_namespace_prefix(namespace_id: NamespaceType) -> Option<&'static str> {
+        Some(match namespace_id {
+            0 => "",
+            1 => "Talk:",
+            2 => "User:",
+            3 => "User_talk:",
+            4 => "Project:",
+            6 => "File:",
+            10 => "Template:",
+            12 => "Help:",
+            14 => "Category:",
+            120 => "Property:",
+            146 => "Lexeme:",
+            _ => return None,
+        })
+    }
+
+    fn encode_title(title: &str) -> String {
+        title
+            .bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' | b'.' | b':' | b'/' => {
+                    (b as char).to_string()
+                }
+                _ => format!("%{b:02X}"),
+            })
+            .collect()
+    }
+
     pub fn as_string(&self, column: &HeaderColumn) -> String {
         if self.wiki == column.wiki && self.namespace_id == column.namespace_id {
             self.title.to_owned()
@@ -29,8 +72,8 @@ impl WikiPage {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Location {
-    lat: f64,
-    lon: f64,
+    pub lat: f64,
+    pub lon: f64,
 }
 
 impl Location {
@@ -43,11 +86,131 @@ impl Location {
     }
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Number {
+    pub value: f64,
+    pub unit: Option<String>,
+}
+
+impl Number {
+    pub fn as_json(&self, column: &HeaderColumn) -> serde_json::Value {
+        if self.unit == column.unit {
+            json!(self.value) // Short version, number only
+        } else {
+            json!(self) // Long version
+        }
+    }
+
+    pub fn as_string(&self, column: &HeaderColumn) -> String {
+        match &self.unit {
+            Some(unit) if self.unit != column.unit => format!("{} {unit}", self.value),
+            _ => format!("{}", self.value),
+        }
+    }
+}
+
+/// A point in time, stored like a Wikidata time value
+/// (`+2001-12-31T00:00:00Z`) with a precision of 9 (year) to 14 (second).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Date {
+    pub time: String,
+    pub precision: u8,
+}
+
+impl Date {
+    pub const PRECISION_YEAR: u8 = 9;
+    pub const PRECISION_MONTH: u8 = 10;
+    pub const PRECISION_DAY: u8 = 11;
+    pub const PRECISION_HOUR: u8 = 12;
+    pub const PRECISION_MINUTE: u8 = 13;
+    pub const PRECISION_SECOND: u8 = 14;
+
+    /// Parses "2001", "2001-12", "2001-12-31", "2001-12-31T12:34:56Z",
+    /// and Wikidata-style "+2001-00-00T00:00:00Z". Precision is derived from the
+    /// last non-zero component.
+    pub fn parse(s: &str) -> Option<Self> {
+        let cap = crate::header::RE_DATE.captures(s.trim())?;
+        let part = |num: usize| -> Option<u32> {
+            match cap.get(num) {
+                Some(m) => m.as_str().parse::<u32>().ok(),
+                None => Some(0),
+            }
+        };
+        let year = cap[1].parse::<i64>().ok()?;
+        let (month, day) = (part(2)?, part(3)?);
+        let (hour, minute, second) = (part(4)?, part(5)?, part(6)?);
+        if month > 12 || day > 31 || hour > 23 || minute > 59 || second > 59 {
+            return None;
+        }
+        let precision = if second > 0 {
+            Self::PRECISION_SECOND
+        } else if minute > 0 {
+            Self::PRECISION_MINUTE
+        } else if hour > 0 {
+            Self::PRECISION_HOUR
+        } else if day > 0 {
+            Self::PRECISION_DAY
+        } else if month > 0 {
+            Self::PRECISION_MONTH
+        } else {
+            Self::PRECISION_YEAR
+        };
+        let sign = if year < 0 { '-' } else { '+' };
+        let year = year.abs();
+        Some(Self {
+            time: format!("{sign}{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z"),
+            precision,
+        })
+    }
+
+    pub fn as_json(&self, _column: &HeaderColumn) -> serde_json::Value {
+        json!(self)
+    }
+
+    pub fn as_string(&self, _column: &HeaderColumn) -> String {
+        let time = self.time.trim_start_matches('+');
+        // Negative years have an extra leading '-'
+        let offset = if time.starts_with('-') { 1 } else { 0 };
+        let year_length = match time[offset..].find('-') {
+            Some(pos) => pos + offset,
+            None => return time.to_string(),
+        };
+        let length = match self.precision {
+            0..=9 => year_length,
+            10 => year_length + 3,
+            11 => year_length + 6,
+            _ => time.len(),
+        };
+        time.get(..length).unwrap_or(time).to_string()
+    }
+
+    /// The date as a calendar date and time, with unknown months and days as the
+    /// first one. None for negative years, and years beyond 9999.
+    pub fn as_naive_datetime(&self) -> Option<chrono::NaiveDateTime> {
+        let cap = crate::header::RE_DATE.captures(&self.time)?;
+        let part = |num: usize| -> u32 {
+            cap.get(num)
+                .and_then(|m| m.as_str().parse::<u32>().ok())
+                .unwrap_or(0)
+        };
+        let year = cap[1]
+            .parse::<i32>()
+            .ok()
+            .filter(|y| (0..=9999).contains(y))?;
+        let date = chrono::NaiveDate::from_ymd_opt(year, part(2).max(1), part(3).max(1))?;
+        date.and_hms_opt(part(4), part(5), part(6))
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Cell {
     WikiPage(WikiPage),
     String(String),
     Location(Location),
+    Number(Number),
+    Date(Date),
+    Boolean(bool),
+    WikidataEntity(String),
 }
 
 impl Cell {
@@ -56,6 +219,75 @@ impl Cell {
             ColumnType::String => Some(Self::String(value.as_str()?.to_string())),
             ColumnType::WikiPage => Self::new_wiki_page(value, column),
             ColumnType::Location => Self::new_location(value),
+            ColumnType::Number => Self::new_number(value, column),
+            ColumnType::Date => Self::new_date(value),
+            ColumnType::Boolean => Self::new_boolean(value),
+            ColumnType::WikidataEntity => Self::new_wikidata_entity(value),
+        }
+    }
+
+    /// Accepts "Q42", "q42", and entity URIs like "http://www.wikidata.org/entity/Q42"
+    fn new_wikidata_entity(value: &serde_json::Value) -> Option<Self> {
+        let s = value.as_str()?.trim();
+        let id = s.rsplit('/').next()?.to_uppercase();
+        if crate::header::RE_WIKIDATA.is_match(&id) {
+            Some(Self::WikidataEntity(id))
+        } else {
+            None
+        }
+    }
+
+    fn new_number(value: &serde_json::Value, column: &HeaderColumn) -> Option<Self> {
+        let number = if let Some(s) = value.as_str() {
+            let cap = crate::header::RE_NUMBER.captures(s.trim())?;
+            let unit = match cap.get(2) {
+                Some(unit) => Some(unit.as_str().to_string()),
+                None => column.unit.to_owned(),
+            };
+            Number {
+                value: cap[1].parse::<f64>().ok()?,
+                unit,
+            }
+        } else if let Some(o) = value.as_object() {
+            let unit = match o.get("unit") {
+                Some(unit) => unit.as_str().map(|s| s.to_string()),
+                None => column.unit.to_owned(),
+            };
+            Number {
+                value: Self::value_as_f64(o.get("value")?)?,
+                unit,
+            }
+        } else {
+            Number {
+                value: value.as_f64()?,
+                unit: column.unit.to_owned(),
+            }
+        };
+        Some(Self::Number(number))
+    }
+
+    fn new_date(value: &serde_json::Value) -> Option<Self> {
+        if let Some(s) = value.as_str() {
+            return Some(Self::Date(Date::parse(s)?));
+        }
+        let mut date = Date::parse(value.get("time")?.as_str()?)?;
+        if let Some(precision) = value.get("precision").and_then(|p| p.as_u64()) {
+            date.precision = precision.min(Date::PRECISION_SECOND as u64) as u8;
+        }
+        Some(Self::Date(date))
+    }
+
+    fn new_boolean(value: &serde_json::Value) -> Option<Self> {
+        if let Some(b) = value.as_bool() {
+            return Some(Self::Boolean(b));
+        }
+        if let Some(i) = value.as_i64() {
+            return Some(Self::Boolean(i != 0));
+        }
+        match value.as_str()?.trim().to_lowercase().as_str() {
+            "true" | "yes" | "y" | "1" => Some(Self::Boolean(true)),
+            "false" | "no" | "n" | "0" => Some(Self::Boolean(false)),
+            _ => None,
         }
     }
 
@@ -125,6 +357,21 @@ impl Cell {
             Cell::String(s) => json!(s),
             Cell::WikiPage(wp) => wp.as_json(column),
             Cell::Location(location) => location.as_json(column),
+            Cell::Number(number) => number.as_json(column),
+            Cell::Date(date) => date.as_json(column),
+            Cell::Boolean(b) => json!(b),
+            Cell::WikidataEntity(id) => json!(id),
+        }
+    }
+
+    /// Link for cells that refer to a page or entity, e.g. for spreadsheet exports
+    pub fn url(&self, column: &HeaderColumn) -> Option<String> {
+        match self {
+            Cell::WikiPage(wp) => wp.url(column),
+            Cell::WikidataEntity(id) => Some(format!(
+                "https://www.wikidata.org/wiki/Special:EntityPage/{id}"
+            )),
+            _ => None,
         }
     }
 
@@ -133,6 +380,10 @@ impl Cell {
             Cell::String(s) => s.to_owned(),
             Cell::WikiPage(wp) => wp.as_string(column),
             Cell::Location(location) => location.as_string(column),
+            Cell::Number(number) => number.as_string(column),
+            Cell::Date(date) => date.as_string(column),
+            Cell::Boolean(b) => format!("{b}"),
+            Cell::WikidataEntity(id) => id.to_owned(),
         }
     }
 }
@@ -148,6 +399,9 @@ mod tests {
             wiki: None,
             string: None,
             namespace_id: None,
+            unit: None,
+            label: None,
+            description: None,
         };
         let j = json!({"title":"Abc","namespace_id":7,"wiki":"frwiki"});
         let c = Cell::new_wiki_page(&j, &column).expect("new_wiki_page failed");
@@ -160,4 +414,124 @@ mod tests {
         assert_eq!(wp.wiki, Some("frwiki".to_string()));
         assert_eq!(wp.as_json(&column), j); // Round trip
     }
+
+    #[test]
+    fn test_wiki_page_url() {
+        let column = HeaderColumn::from_value(
+            &json!({"column_type":"WikiPage","wiki":"commonswiki","namespace_id":6}),
+        )
+        .unwrap();
+        let wp = WikiPage {
+            title: "Foo bar?.jpg".to_string(),
+            namespace_id: None,
+            wiki: None,
+        };
+        assert_eq!(
+            wp.url(&column),
+            Some("https://commons.wikimedia.org/wiki/File:Foo_bar%3F.jpg".to_string())
+        );
+        let wp = WikiPage {
+            title: "X".to_string(),
+            namespace_id: Some(7),
+            wiki: None,
+        };
+        assert_eq!(wp.url(&column), None);
+    }
+
+    #[test]
+    fn test_new_number() {
+        let column = HeaderColumn {
+            column_type: ColumnType::Number,
+            wiki: None,
+            string: None,
+            namespace_id: None,
+            unit: Some("kg".to_string()),
+            label: None,
+            description: None,
+        };
+        let c = Cell::from_value(&json!("12.5"), &column).expect("from_value failed");
+        assert_eq!(c.as_json(&column), json!(12.5));
+        assert_eq!(c.as_string(&column), "12.5");
+        let c = Cell::from_value(&json!("3 m"), &column).expect("from_value failed");
+        let j = json!({"value":3.0,"unit":"m"});
+        assert_eq!(c.as_json(&column), j);
+        assert_eq!(c.as_string(&column), "3 m");
+        let c = Cell::from_value(&j, &column).expect("from_value failed");
+        assert_eq!(c.as_json(&column), j); // Round trip
+        assert!(Cell::from_value(&json!("abc"), &column).is_none());
+    }
+
+    #[test]
+    fn test_new_date() {
+        let column = HeaderColumn {
+            column_type: ColumnType::Date,
+            wiki: None,
+            string: None,
+            namespace_id: None,
+            unit: None,
+            label: None,
+            description: None,
+        };
+        let c = Cell::from_value(&json!("2001-12"), &column).expect("from_value failed");
+        let j = json!({"time":"+2001-12-00T00:00:00Z","precision":10});
+        assert_eq!(c.as_json(&column), j);
+        assert_eq!(c.as_string(&column), "2001-12");
+        let c = Cell::from_value(&j, &column).expect("from_value failed");
+        assert_eq!(c.as_json(&column), j); // Round trip
+        let j = json!({"time":"+1850-00-00T00:00:00Z","precision":7});
+        let c = Cell::from_value(&j, &column).expect("from_value failed");
+        assert_eq!(c.as_string(&column), "1850");
+        assert!(Cell::from_value(&json!("2001-13-01"), &column).is_none());
+    }
+
+    #[test]
+    fn test_date_as_naive_datetime() {
+        let date = |s: &str| Date::parse(s).expect("parse failed").as_naive_datetime();
+        let expected =
+            chrono::NaiveDate::from_ymd_opt(2001, 12, 1).and_then(|d| d.and_hms_opt(0, 0, 0));
+        assert_eq!(date("2001-12"), expected);
+        let expected =
+            chrono::NaiveDate::from_ymd_opt(2001, 12, 31).and_then(|d| d.and_hms_opt(12, 34, 56));
+        assert_eq!(date("2001-12-31T12:34:56Z"), expected);
+        assert_eq!(date("-500"), None);
+        assert_eq!(date("+12000-01-01"), None);
+    }
+
+    #[test]
+    fn test_new_boolean() {
+        let column = HeaderColumn {
+            column_type: ColumnType::Boolean,
+            wiki: None,
+            string: None,
+            namespace_id: None,
+            unit: None,
+            label: None,
+            description: None,
+        };
+        let c = Cell::from_value(&json!("Yes"), &column).expect("from_value failed");
+        assert_eq!(c.as_json(&column), json!(true));
+        assert_eq!(c.as_string(&column), "true");
+        let c = Cell::from_value(&json!(0), &column).expect("from_value failed");
+        assert_eq!(c.as_json(&column), json!(false));
+        assert!(Cell::from_value(&json!("maybe"), &column).is_none());
+    }
+
+    #[test]
+    fn test_new_wikidata_entity() {
+        let column = HeaderColumn {
+            column_type: ColumnType::WikidataEntity,
+            wiki: None,
+            string: None,
+            namespace_id: None,
+            unit: None,
+            label: None,
+            description: None,
+        };
+        let c = Cell::from_value(&json!("q42"), &column).expect("from_value failed");
+        assert_eq!(c.as_json(&column), json!("Q42"));
+        let c = Cell::from_value(&json!("http://www.wikidata.org/entity/L7"), &column)
+            .expect("from_value failed");
+        assert_eq!(c.as_string(&column), "L7");
+        assert!(Cell::from_value(&json!("X42"), &column).is_none());
+    }
 }
@@ -9,6 +9,10 @@ pub enum ColumnType {
     String,
     WikiPage,
     Location,
+    Number,
+    Date,
+    Boolean,
+    WikidataEntity,
 }
 impl FromStr for ColumnType {
     type Err = ParsColumnTypeError;
@@ -18,6 +22,10 @@ impl FromStr for ColumnType {
             "String" => Ok(Self::String),
             "WikiPage" => Ok(Self::WikiPage),
             "Location" => Ok(Self::Location),
+            "Number" => Ok(Self::Number),
+            "Date" => Ok(Self::Date),
+            "Boolean" => Ok(Self::Boolean),
+            "WikidataEntity" => Ok(Self::WikidataEntity),
             _ => Err(ParsColumnTypeError),
         }
     }
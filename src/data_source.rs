@@ -1,8 +1,10 @@
 use crate::cell::*;
-use crate::column::ColumnType;
-use crate::data_source_as_file::DataSourceAsFile;
-use crate::data_source_line_converter::DataSourceLineConverter;
+use crate::data_source_as_file::{
+    sparql_query_url, DataSourceAsFile, SourceLocation, SPARQL_RESULTS_JSON,
+};
+use crate::data_source_line_converter::{string_column, DataSourceLineConverter, RowReader};
 use crate::row::Row;
+use crate::source_cache::SourceCache;
 use crate::{app_state::AppState, header::*, GulpError};
 use mysql_async::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -11,6 +13,9 @@ use std::fs::File;
 use std::io::{BufReader, Read, Seek};
 use std::sync::Arc;
 
+/// Previews use a download of the source up to this old (seconds) without asking the server
+const PREVIEW_MAX_AGE: i64 = 60 * 60;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DataSourceTypeUrl {}
 
@@ -20,17 +25,38 @@ pub struct DataSourceTypeFile {}
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DataSourceTypePagePile {}
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DataSourceTypeSparql {}
+
 #[derive(Clone, Debug, Serialize)]
 pub struct FileWithHeader {
     pub headers: Vec<HeaderColumn>,
+    /// Skip the first record and use it to label the columns (CSV, TSV, Excel)
+    pub first_row_is_header: bool,
+    /// Excel and ODS only: worksheet name or index, and the part of the sheet to read
+    pub excel_sheet: Option<String>,
+    pub excel_range: Option<SheetRange>,
+    /// JSON only: pointer to the array of objects in the document, e.g. "/data/items"
+    pub json_pointer: Option<String>,
     #[serde(skip)]
     pub file: Arc<File>,
 }
 
+impl FileWithHeader {
+    /// A new handle to the file, positioned at the start
+    pub fn reopen(&self) -> Result<File, GulpError> {
+        let mut file = self.file.try_clone()?;
+        file.rewind()?;
+        Ok(file)
+    }
+}
+
 #[derive(Clone, Debug, Serialize)]
 pub struct CellSet {
     pub headers: Vec<HeaderColumn>,
     pub rows: Vec<Row>,
+    /// The headers were given by the file itself (JSONL schema line), so they are not guessed
+    pub headers_from_file: bool,
 }
 
 impl CellSet {
@@ -47,24 +73,13 @@ impl CellSet {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DataSourceFormatJSONL {}
 
-impl DataSourceFormatJSONL {
-    pub fn guess_headers(&self, lines: &[String]) -> Vec<HeaderColumn> {
-        let columns = lines
-            .iter()
-            .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
-            .filter_map(|v| v.as_array().cloned())
-            .map(|array| array.len())
-            .max()
-            .unwrap_or(0);
-        let header = HeaderColumn {
-            column_type: ColumnType::String,
-            wiki: None,
-            string: None,
-            namespace_id: None,
-        };
-        std::iter::repeat(header).take(columns).collect()
-    }
-}
+/// JSON Lines with one object per line; object keys become columns
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DataSourceFormatJSONLObjects {}
+
+/// A JSON document containing an array of objects; object keys become columns
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DataSourceFormatJSON {}
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DataSourceFormatExcel {}
@@ -78,16 +93,84 @@ impl DataSourceFormatExcel {
             .map(|array| array.len())
             .max()
             .unwrap_or(0);
-        let header = HeaderColumn {
-            column_type: ColumnType::String,
-            wiki: None,
-            string: None,
-            namespace_id: None,
+        vec![string_column(); columns]
+    }
+}
+
+/// A part of a worksheet in A1 notation, e.g. "B3" (from B3 to the end) or "B3:F100".
+/// Rows and columns are zero-based.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct SheetRange {
+    pub first_row: usize,
+    pub first_column: usize,
+    pub last_row: Option<usize>,
+    pub last_column: Option<usize>,
+}
+
+impl SheetRange {
+    pub fn new(s: &str) -> Option<Self> {
+        let mut parts = s.trim().split(':');
+        let (first_column, first_row) = Self::parse_cell(parts.next()?)?;
+        let (last_column, last_row) = match parts.next() {
+            Some(part) => {
+                let (column, row) = Self::parse_cell(part)?;
+                if column < first_column || row < first_row {
+                    return None;
+                }
+                (Some(column), Some(row))
+            }
+            None => (None, None),
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self {
+            first_row,
+            first_column,
+            last_row,
+            last_column,
+        })
+    }
+
+    /// "B3" => (1,2)
+    fn parse_cell(s: &str) -> Option<(usize, usize)> {
+        let s = s.trim().to_uppercase();
+        let letters = s.chars().take_while(|c| c.is_ascii_uppercase()).count();
+        if letters == 0 || letters > 3 {
+            return None;
+        }
+        let column = s[..letters]
+            .chars()
+            .fold(0, |acc, c| acc * 26 + (c as usize - 'A' as usize + 1));
+        let row = s[letters..].parse::<usize>().ok()?;
+        if row == 0 {
+            return None;
+        }
+        Some((column - 1, row - 1))
+    }
+
+    /// Applies the row part of the range to an iterator over rows
+    pub fn rows<I: Iterator>(&self, rows: I) -> impl Iterator<Item = I::Item> {
+        let take = match self.last_row {
+            Some(last_row) => last_row - self.first_row + 1,
+            None => usize::MAX,
+        };
+        rows.skip(self.first_row).take(take)
+    }
+
+    /// The cells of a row that are within the range
+    pub fn columns<'a, T>(&self, row: &'a [T]) -> &'a [T] {
+        let end = match self.last_column {
+            Some(last_column) => (last_column + 1).min(row.len()),
+            None => row.len(),
         };
-        std::iter::repeat(header).take(columns).collect()
+        row.get(self.first_column..end).unwrap_or_default()
     }
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DataSourceFormatODS {}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DataSourceFormatPagePile {}
 
@@ -122,6 +205,10 @@ impl DataSourceFormatPagePile {
     }
 }
 
+/// SPARQL query results in the JSON format, see https://www.w3.org/TR/sparql11-results-json/
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DataSourceFormatSparqlJSON {}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DataSourceFormatCSV {}
 
@@ -133,8 +220,12 @@ pub enum DataSourceFormat {
     TSV,
     CSV,
     JSONL,
+    JSONLOBJECTS,
+    JSON,
     PAGEPILE,
     EXCEL,
+    ODS,
+    SPARQLJSON,
 }
 
 impl DataSourceFormat {
@@ -143,8 +234,12 @@ impl DataSourceFormat {
             "CSV" => Some(Self::CSV),
             "TSV" => Some(Self::TSV),
             "JSONL" => Some(Self::JSONL),
+            "JSONL_OBJECTS" => Some(Self::JSONLOBJECTS),
+            "JSON" => Some(Self::JSON),
             "PAGEPILE" => Some(Self::PAGEPILE),
             "XLS" => Some(Self::EXCEL),
+            "ODS" => Some(Self::ODS),
+            "SPARQL_JSON" => Some(Self::SPARQLJSON),
             _ => None,
         }
     }
@@ -154,8 +249,12 @@ impl DataSourceFormat {
             Self::CSV => Box::new(DataSourceFormatCSV {}),
             Self::TSV => Box::new(DataSourceFormatTSV {}),
             Self::JSONL => Box::new(DataSourceFormatJSONL {}),
+            Self::JSONLOBJECTS => Box::new(DataSourceFormatJSONLObjects {}),
+            Self::JSON => Box::new(DataSourceFormatJSON {}),
             Self::PAGEPILE => Box::new(DataSourceFormatPagePile {}),
             Self::EXCEL => Box::new(DataSourceFormatExcel {}),
+            Self::ODS => Box::new(DataSourceFormatODS {}),
+            Self::SPARQLJSON => Box::new(DataSourceFormatSparqlJSON {}),
         }
     }
 }
@@ -166,8 +265,12 @@ impl fmt::Display for DataSourceFormat {
             Self::CSV => write!(f, "CSV"),
             Self::TSV => write!(f, "TSV"),
             Self::JSONL => write!(f, "JSONL"),
+            Self::JSONLOBJECTS => write!(f, "JSONL_OBJECTS"),
+            Self::JSON => write!(f, "JSON"),
             Self::PAGEPILE => write!(f, "PAGEPILE"),
             Self::EXCEL => write!(f, "XLS"),
+            Self::ODS => write!(f, "ODS"),
+            Self::SPARQLJSON => write!(f, "SPARQL_JSON"),
         }
     }
 }
@@ -177,6 +280,7 @@ pub enum DataSourceType {
     URL,
     FILE,
     PAGEPILE,
+    SPARQL,
 }
 
 impl DataSourceType {
@@ -185,6 +289,7 @@ impl DataSourceType {
             "URL" => Some(Self::URL),
             "FILE" => Some(Self::FILE),
             "PAGEPILE" => Some(Self::PAGEPILE),
+            "SPARQL" => Some(Self::SPARQL),
             _ => None,
         }
     }
@@ -194,6 +299,7 @@ impl DataSourceType {
             Self::URL => Box::new(DataSourceTypeUrl {}),
             Self::FILE => Box::new(DataSourceTypeFile {}),
             Self::PAGEPILE => Box::new(DataSourceTypePagePile {}),
+            Self::SPARQL => Box::new(DataSourceTypeSparql {}),
         }
     }
 }
@@ -204,6 +310,35 @@ impl fmt::Display for DataSourceType {
             Self::URL => write!(f, "URL"),
             Self::FILE => write!(f, "FILE"),
             Self::PAGEPILE => write!(f, "PAGEPILE"),
+            Self::SPARQL => write!(f, "SPARQL"),
+        }
+    }
+}
+
+/// How `List::update_from_source` treats rows already in the list.
+/// APPEND only adds rows that are not in the list yet; SYNC matches rows by `key_column`,
+/// updates changed ones in place, and deletes rows that are no longer in the source.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum DataSourceUpdateMode {
+    APPEND,
+    SYNC,
+}
+
+impl DataSourceUpdateMode {
+    pub fn new(s: &str) -> Option<Self> {
+        match s.trim().to_uppercase().as_str() {
+            "APPEND" => Some(Self::APPEND),
+            "SYNC" => Some(Self::SYNC),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DataSourceUpdateMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::APPEND => write!(f, "APPEND"),
+            Self::SYNC => write!(f, "SYNC"),
         }
     }
 }
@@ -216,12 +351,22 @@ pub struct DataSource {
     pub source_format: DataSourceFormat,
     pub location: String,
     pub user_id: DbId,
+    pub update_mode: DataSourceUpdateMode,
+    pub key_column: Option<usize>,
+    pub first_row_is_header: bool,
+    pub excel_sheet: Option<String>,
+    pub excel_range: Option<String>,
+    pub json_pointer: Option<String>,
+    pub refresh_schedule: Option<String>, // see `RefreshSchedule`; only for URL, PAGEPILE and SPARQL
+    pub auto_snapshot: bool,              // snapshot the list after a scheduled refresh
+    pub archive_member: Option<String>,   // file to read from a zip archive
 }
 
 impl DataSource {
     pub fn from_row(row: &mysql_async::Row) -> Option<Self> {
         let source_type: String = row.get(2)?;
         let source_format: String = row.get(3)?;
+        let update_mode: String = row.get(6)?;
         Some(Self {
             id: row.get(0)?,
             list_id: row.get(1)?,
@@ -229,11 +374,20 @@ impl DataSource {
             source_format: DataSourceFormat::new(&source_format)?,
             location: row.get(4)?,
             user_id: row.get(5)?,
+            update_mode: DataSourceUpdateMode::new(&update_mode)?,
+            key_column: row.get(7)?,
+            first_row_is_header: row.get(8)?,
+            excel_sheet: row.get(9)?,
+            excel_range: row.get(10)?,
+            json_pointer: row.get(11)?,
+            refresh_schedule: row.get(12)?,
+            auto_snapshot: row.get(13)?,
+            archive_member: row.get(14)?,
         })
     }
 
     pub async fn from_db(app: &Arc<AppState>, source_id: DbId) -> Option<Self> {
-        let sql = r#"SELECT id,list_id,source_type,source_format,location,user_id FROM data_source WHERE id=:source_id"#;
+        let sql = r#"SELECT id,list_id,source_type,source_format,location,user_id,update_mode,key_column,first_row_is_header,excel_sheet,excel_range,json_pointer,refresh_schedule,auto_snapshot,archive_member FROM data_source WHERE id=:source_id"#;
         app.get_gulp_conn()
             .await
             .ok()?
@@ -253,11 +407,20 @@ impl DataSource {
         let source_format = self.source_format.to_string();
         let location = self.location.to_owned();
         let user_id = self.user_id;
-        let sql = "INSERT INTO `data_source` (list_id,source_type,source_format,location,user_id) VALUES (:list_id,:source_type,:source_format,:location,:user_id)";
+        let update_mode = self.update_mode.to_string();
+        let key_column = self.key_column;
+        let first_row_is_header = self.first_row_is_header;
+        let excel_sheet = self.excel_sheet.to_owned();
+        let excel_range = self.excel_range.to_owned();
+        let json_pointer = self.json_pointer.to_owned();
+        let refresh_schedule = self.refresh_schedule.to_owned();
+        let auto_snapshot = self.auto_snapshot;
+        let archive_member = self.archive_member.to_owned();
+        let sql = "INSERT INTO `data_source` (list_id,source_type,source_format,location,user_id,update_mode,key_column,first_row_is_header,excel_sheet,excel_range,json_pointer,refresh_schedule,auto_snapshot,archive_member) VALUES (:list_id,:source_type,:source_format,:location,:user_id,:update_mode,:key_column,:first_row_is_header,:excel_sheet,:excel_range,:json_pointer,:refresh_schedule,:auto_snapshot,:archive_member)";
         let mut conn = app.get_gulp_conn().await.ok()?;
         conn.exec_drop(
             sql,
-            params! {list_id,source_type,source_format,location,user_id},
+            params! {list_id,source_type,source_format,location,user_id,update_mode,key_column,first_row_is_header,excel_sheet,excel_range,json_pointer,refresh_schedule,auto_snapshot,archive_member},
         )
         .await
         .ok()?;
@@ -265,18 +428,81 @@ impl DataSource {
         Some(self.id)
     }
 
-    pub async fn get_cells(&self, limit: Option<usize>) -> Result<CellSet, GulpError> {
-        let mut header_file = self.get_line_set().await?;
+    pub async fn set_refresh_schedule(
+        &mut self,
+        app: &Arc<AppState>,
+        refresh_schedule: Option<String>,
+        auto_snapshot: bool,
+    ) -> Result<(), GulpError> {
+        self.refresh_schedule = refresh_schedule;
+        self.auto_snapshot = auto_snapshot;
+        let source_id = self.id;
+        let refresh_schedule = self.refresh_schedule.to_owned();
+        let sql = "UPDATE `data_source` SET refresh_schedule=:refresh_schedule,auto_snapshot=:auto_snapshot WHERE id=:source_id";
+        app.get_gulp_conn()
+            .await?
+            .exec_drop(sql, params! {source_id,refresh_schedule,auto_snapshot})
+            .await?;
+        Ok(())
+    }
+
+    /// All URL, PagePile and SPARQL sources with a refresh schedule
+    pub async fn get_scheduled(app: &Arc<AppState>) -> Result<Vec<Self>, GulpError> {
+        let sql = r#"SELECT id,list_id,source_type,source_format,location,user_id,update_mode,key_column,first_row_is_header,excel_sheet,excel_range,json_pointer,refresh_schedule,auto_snapshot,archive_member FROM data_source WHERE refresh_schedule IS NOT NULL AND source_type IN ('URL','PAGEPILE','SPARQL')"#;
+        let sources = app
+            .get_gulp_conn()
+            .await?
+            .exec_iter(sql, ())
+            .await?
+            .map_and_drop(|row| Self::from_row(&row))
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+        Ok(sources)
+    }
+
+    /// Only sources that can change upstream can be refreshed on a schedule
+    pub fn can_be_scheduled(&self) -> bool {
+        matches!(
+            self.source_type,
+            DataSourceType::URL | DataSourceType::PAGEPILE | DataSourceType::SPARQL
+        )
+    }
+
+    pub async fn get_cells(
+        &self,
+        app: &Arc<AppState>,
+        limit: Option<usize>,
+    ) -> Result<CellSet, GulpError> {
+        let mut header_file = self.get_line_set(app, Some(PREVIEW_MAX_AGE)).await?;
         let lh = self.source_format.line_converter();
         lh.get_cells(&mut header_file, limit)
     }
 
-    pub async fn guess_headers(&self, limit: Option<usize>) -> Result<CellSet, GulpError> {
-        let mut header_file = self.get_line_set().await?;
+    /// Reads the rows of a file from `file_with_header` one by one, without loading the whole source into memory
+    pub fn get_row_reader(
+        &self,
+        header_file: &mut FileWithHeader,
+    ) -> Result<Box<dyn RowReader>, GulpError> {
+        self.source_format
+            .line_converter()
+            .get_row_reader(header_file)
+    }
+
+    pub async fn guess_headers(
+        &self,
+        app: &Arc<AppState>,
+        limit: Option<usize>,
+    ) -> Result<CellSet, GulpError> {
+        let mut header_file = self.get_line_set(app, Some(PREVIEW_MAX_AGE)).await?;
         let cell_set = self
             .source_format
             .line_converter()
             .get_cells(&mut header_file, limit)?;
+        if cell_set.headers_from_file {
+            return Ok(cell_set);
+        }
         let headers = cell_set.headers.to_owned();
         let futures: Vec<_> = headers
             .iter()
@@ -295,11 +521,115 @@ impl DataSource {
         Ok(cell_set)
     }
 
-    async fn get_line_set(&self) -> Result<FileWithHeader, GulpError> {
-        let lh = self.source_type.line_handler();
+    /// Names of the worksheets; empty for formats other than Excel and ODS
+    pub async fn get_sheet_names(&self, app: &Arc<AppState>) -> Result<Vec<String>, GulpError> {
+        match self.source_format {
+            DataSourceFormat::EXCEL => {
+                let mut header_file = self.get_line_set(app, Some(PREVIEW_MAX_AGE)).await?;
+                DataSourceFormatExcel {}.get_sheet_names(&mut header_file)
+            }
+            DataSourceFormat::ODS => {
+                let mut header_file = self.get_line_set(app, Some(PREVIEW_MAX_AGE)).await?;
+                DataSourceFormatODS {}.get_sheet_names(&mut header_file)
+            }
+            _ => Ok(vec![]),
+        }
+    }
+
+    /// Fetches the source file, see `fetch_file`
+    async fn get_line_set(
+        &self,
+        app: &Arc<AppState>,
+        max_age: Option<i64>,
+    ) -> Result<FileWithHeader, GulpError> {
+        let (file, _) = self.fetch_file(app, max_age).await?;
+        self.file_with_header(app, file).await
+    }
+
+    /// Opens a local file, or downloads the source. Downloads are cached; the cache is
+    /// returned as well, and used as it is if it was checked at most `max_age` seconds ago.
+    pub async fn fetch_file(
+        &self,
+        app: &Arc<AppState>,
+        max_age: Option<i64>,
+    ) -> Result<(File, Option<SourceCache>), GulpError> {
+        let location = self.source_type.line_handler().location(self)?;
+        match location {
+            SourceLocation::Path(path) => Ok((File::open(path)?, None)),
+            SourceLocation::Url(url) => {
+                let cache = SourceCache::fetch(app, self.id, &url, None, max_age).await?;
+                Ok((cache.open()?, Some(cache)))
+            }
+            SourceLocation::Sparql { endpoint, query } => {
+                let url = sparql_query_url(&endpoint, &query)?;
+                let accept = Some(SPARQL_RESULTS_JSON);
+                let cache = SourceCache::fetch(app, self.id, &url, accept, max_age).await?;
+                Ok((cache.open()?, Some(cache)))
+            }
+        }
+    }
+
+    /// Wraps a file from `fetch_file`, decompressed, with the settings of this source.
+    /// Decompression runs on a blocking thread, and is limited like downloads.
+    pub async fn file_with_header(
+        &self,
+        app: &Arc<AppState>,
+        file: File,
+    ) -> Result<FileWithHeader, GulpError> {
+        let excel_range = match &self.excel_range {
+            Some(range) => Some(
+                SheetRange::new(range).ok_or_else(|| format!("Invalid Excel range '{range}'"))?,
+            ),
+            None => None,
+        };
+        let source = self.clone();
+        let max_bytes = app.fetcher.max_bytes();
+        let file = tokio::task::spawn_blocking(move || {
+            source
+                .source_type
+                .line_handler()
+                .uncompressed(file, &source, max_bytes)
+        })
+        .await
+        .map_err(|e| e.to_string())??;
         Ok(FileWithHeader {
             headers: vec![],
-            file: Arc::new(lh.as_file(self)?),
+            first_row_is_header: self.first_row_is_header,
+            excel_sheet: self.excel_sheet.to_owned(),
+            excel_range,
+            json_pointer: self.json_pointer.to_owned(),
+            file: Arc::new(file),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sheet_range() {
+        let range = SheetRange::new("B3:AA10").unwrap();
+        assert_eq!(range.first_row, 2);
+        assert_eq!(range.first_column, 1);
+        assert_eq!(range.last_row, Some(9));
+        assert_eq!(range.last_column, Some(26));
+        assert_eq!(SheetRange::new("a1"), Some(SheetRange::default()));
+        assert!(SheetRange::new("B0").is_none());
+        assert!(SheetRange::new("C3:B5").is_none());
+        assert!(SheetRange::new("3").is_none());
+
+        let range = SheetRange::new("B2:C3").unwrap();
+        let rows = [
+            vec![1, 2, 3, 4],
+            vec![5, 6, 7, 8],
+            vec![9, 10, 11, 12],
+            vec![13],
+        ];
+        let cells: Vec<&[i32]> = range
+            .rows(rows.iter())
+            .map(|row| range.columns(row))
+            .collect();
+        assert_eq!(cells, vec![&[6, 7][..], &[10, 11][..]]);
+    }
+}
@@ -1,38 +1,335 @@
 use crate::data_source::*;
 use crate::GulpError;
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use reqwest::Url;
 use std::fs::File;
-use std::io::{self, Seek};
+use std::io::{self, BufReader, Read, Seek};
+use zip::ZipArchive;
+
+/// Where the file of a data source comes from
+#[derive(Clone, Debug, PartialEq)]
+pub enum SourceLocation {
+    Path(String),
+    Url(String),
+    Sparql { endpoint: String, query: String },
+}
 
 pub trait DataSourceAsFile {
-    fn as_file(&self, ds: &DataSource) -> Result<File, GulpError>;
+    fn location(&self, ds: &DataSource) -> Result<SourceLocation, GulpError>;
 
-    fn file_from_url(&self, url: &str) -> Result<File, GulpError> {
-        // TODO this should ideally by reqwest/async, but async traits are difficult the moment
-        let mut reader = ureq::get(url).call()?.into_reader();
-        let mut file = tempfile::tempfile()?;
-        io::copy(&mut reader, &mut file)?;
-        file.rewind()?;
-        Ok(file)
+    /// The content of a file from `location`, decompressed if need be, up to `max_bytes`
+    fn uncompressed(&self, file: File, ds: &DataSource, max_bytes: u64) -> Result<File, GulpError> {
+        uncompressed(file, ds, max_bytes)
     }
 }
 
 impl DataSourceAsFile for DataSourceTypeUrl {
-    fn as_file(&self, ds: &DataSource) -> Result<File, GulpError> {
-        let url = &ds.location;
-        self.file_from_url(url)
+    fn location(&self, ds: &DataSource) -> Result<SourceLocation, GulpError> {
+        Ok(SourceLocation::Url(ds.location.to_owned()))
     }
 }
 
 impl DataSourceAsFile for DataSourceTypeFile {
-    fn as_file(&self, ds: &DataSource) -> Result<File, GulpError> {
-        Ok(File::open(&ds.location)?)
+    fn location(&self, ds: &DataSource) -> Result<SourceLocation, GulpError> {
+        Ok(SourceLocation::Path(ds.location.to_owned()))
     }
 }
 
 impl DataSourceAsFile for DataSourceTypePagePile {
-    fn as_file(&self, ds: &DataSource) -> Result<File, GulpError> {
+    fn location(&self, ds: &DataSource) -> Result<SourceLocation, GulpError> {
         let id = ds.location.parse::<usize>()?;
         let url = format!("https://pagepile.toolforge.org/api.php?id={id}&action=get_data&doit&format=json&metadata=1");
-        self.file_from_url(&url)
+        Ok(SourceLocation::Url(url))
+    }
+}
+
+/// Used if the location of a SPARQL source does not start with an endpoint URL
+pub const DEFAULT_SPARQL_ENDPOINT: &str = "https://query.wikidata.org/sparql";
+
+/// Media type of SPARQL query results in JSON
+pub const SPARQL_RESULTS_JSON: &str = "application/sparql-results+json";
+
+/// The URL to run `query` on `endpoint` with a GET request
+pub fn sparql_query_url(endpoint: &str, query: &str) -> Result<String, GulpError> {
+    let url = Url::parse_with_params(endpoint, &[("query", query)])
+        .map_err(|e| format!("Invalid SPARQL endpoint '{endpoint}': {e}"))?;
+    Ok(url.to_string())
+}
+
+impl DataSourceAsFile for DataSourceTypeSparql {
+    /// The location is the query, optionally preceded by a line with the endpoint URL
+    fn location(&self, ds: &DataSource) -> Result<SourceLocation, GulpError> {
+        let location = ds.location.trim();
+        let (first_line, rest) = location.split_once('\n').unwrap_or((location, ""));
+        let first_line = first_line.trim();
+        let is_url = first_line.starts_with("http://") || first_line.starts_with("https://");
+        let (endpoint, query) = if is_url {
+            (first_line, rest.trim())
+        } else {
+            (DEFAULT_SPARQL_ENDPOINT, location)
+        };
+        if query.is_empty() {
+            return Err("SPARQL source has no query".into());
+        }
+        Ok(SourceLocation::Sparql {
+            endpoint: endpoint.to_string(),
+            query: query.to_string(),
+        })
+    }
+}
+
+/// Compressed or archived input, recognized by its first bytes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Bzip2,
+    Zip,
+}
+
+impl Compression {
+    pub fn detect(file: &mut File) -> Result<Self, GulpError> {
+        let mut magic = [0; 4];
+        let mut len = 0;
+        while len < magic.len() {
+            match file.read(&mut magic[len..])? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        file.rewind()?;
+        let magic = &magic[..len];
+        Ok(if magic.starts_with(&[0x1f, 0x8b]) {
+            Self::Gzip
+        } else if magic.starts_with(b"BZh") {
+            Self::Bzip2
+        } else if magic.starts_with(b"PK\x03\x04") {
+            Self::Zip
+        } else {
+            Self::None
+        })
+    }
+}
+
+/// Returns the uncompressed content of a source file. From zip archives, this is the
+/// `archive_member` of the source, or the only file in the archive. XLSX and ODS files
+/// are zip archives themselves, so they are only unpacked with an `archive_member`.
+/// Uncompressed content larger than `max_bytes` is an error.
+pub fn uncompressed(mut file: File, ds: &DataSource, max_bytes: u64) -> Result<File, GulpError> {
+    let compression = Compression::detect(&mut file)?;
+    let mut reader: Box<dyn Read> = match compression {
+        Compression::None => return Ok(file),
+        Compression::Gzip => Box::new(MultiGzDecoder::new(BufReader::new(file))),
+        Compression::Bzip2 => Box::new(MultiBzDecoder::new(BufReader::new(file))),
+        Compression::Zip => {
+            let is_office_format = matches!(
+                ds.source_format,
+                DataSourceFormat::EXCEL | DataSourceFormat::ODS
+            );
+            if is_office_format && ds.archive_member.is_none() {
+                return Ok(file);
+            }
+            return unzip_member(file, ds, max_bytes);
+        }
+    };
+    copy_limited(&mut reader, ds, max_bytes)
+}
+
+/// Copies `reader` to a temporary file, failing after `max_bytes`
+fn copy_limited(reader: &mut dyn Read, ds: &DataSource, max_bytes: u64) -> Result<File, GulpError> {
+    let mut out = tempfile::tempfile()?;
+    let bytes = io::copy(&mut reader.take(max_bytes.saturating_add(1)), &mut out)?;
+    if bytes > max_bytes {
+        return Err(GulpError::FetchTooLarge(
+            format!("{} (uncompressed)", ds.location),
+            max_bytes,
+        ));
+    }
+    out.rewind()?;
+    Ok(out)
+}
+
+fn unzip_member(file: File, ds: &DataSource, max_bytes: u64) -> Result<File, GulpError> {
+    let member = ds.archive_member.as_deref();
+    let mut archive = ZipArchive::new(BufReader::new(file))?;
+    let name = match member {
+        Some(member) => member.to_string(),
+        None => {
+            let files: Vec<&str> = archive
+                .file_names()
+                .filter(|name| !name.ends_with('/'))
+                .collect();
+            match files.as_slice() {
+                [name] => name.to_string(),
+                [] => return Err("Zip archive is empty".into()),
+                _ => {
+                    return Err(format!(
+                        "Zip archive has several files, please choose one as archive_member: {}",
+                        files.join(", ")
+                    )
+                    .into())
+                }
+            }
+        }
+    };
+    let mut entry = archive
+        .by_name(&name)
+        .map_err(|_| format!("No file '{name}' in zip archive"))?;
+    copy_limited(&mut entry, ds, max_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    const CSV: &str = "name,value\nfoo,1\nbar,2\n";
+    const MAX_BYTES: u64 = 1 << 20;
+
+    fn source(source_format: DataSourceFormat, archive_member: Option<&str>) -> DataSource {
+        DataSource {
+            id: 0,
+            list_id: 0,
+            source_type: DataSourceType::FILE,
+            source_format,
+            location: String::new(),
+            user_id: 0,
+            update_mode: DataSourceUpdateMode::APPEND,
+            key_column: None,
+            first_row_is_header: true,
+            excel_sheet: None,
+            excel_range: None,
+            json_pointer: None,
+            refresh_schedule: None,
+            auto_snapshot: false,
+            archive_member: archive_member.map(|s| s.to_string()),
+        }
+    }
+
+    fn file_with(data: &[u8]) -> File {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(data).unwrap();
+        file.rewind().unwrap();
+        file
+    }
+
+    fn zip_with(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(io::Cursor::new(vec![]));
+        for (name, content) in files {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn read_all(file: Result<File, GulpError>) -> String {
+        let mut ret = String::new();
+        file.unwrap().read_to_string(&mut ret).unwrap();
+        ret
+    }
+
+    #[test]
+    fn test_sparql_location() {
+        let sparql = |location: &str| {
+            let ds = DataSource {
+                source_type: DataSourceType::SPARQL,
+                location: location.to_string(),
+                ..source(DataSourceFormat::SPARQLJSON, None)
+            };
+            DataSourceTypeSparql {}.location(&ds)
+        };
+        assert_eq!(
+            sparql("SELECT ?q { ?q wdt:P31 wd:Q5 }\nLIMIT 3").unwrap(),
+            SourceLocation::Sparql {
+                endpoint: DEFAULT_SPARQL_ENDPOINT.to_string(),
+                query: "SELECT ?q { ?q wdt:P31 wd:Q5 }\nLIMIT 3".to_string(),
+            }
+        );
+        assert_eq!(
+            sparql("https://example.org/sparql\nSELECT ?q { ?q ?p ?o }").unwrap(),
+            SourceLocation::Sparql {
+                endpoint: "https://example.org/sparql".to_string(),
+                query: "SELECT ?q { ?q ?p ?o }".to_string(),
+            }
+        );
+        assert!(sparql("https://example.org/sparql\n").is_err());
+    }
+
+    #[test]
+    fn test_uncompressed() {
+        let csv = source(DataSourceFormat::CSV, None);
+        assert_eq!(
+            read_all(uncompressed(file_with(CSV.as_bytes()), &csv, MAX_BYTES)),
+            CSV
+        );
+
+        let mut gz = GzEncoder::new(vec![], flate2::Compression::default());
+        gz.write_all(CSV.as_bytes()).unwrap();
+        let gz = gz.finish().unwrap();
+        assert_eq!(read_all(uncompressed(file_with(&gz), &csv, MAX_BYTES)), CSV);
+
+        let mut bz = bzip2::write::BzEncoder::new(vec![], bzip2::Compression::default());
+        bz.write_all(CSV.as_bytes()).unwrap();
+        let bz = bz.finish().unwrap();
+        assert_eq!(read_all(uncompressed(file_with(&bz), &csv, MAX_BYTES)), CSV);
+
+        let single = zip_with(&[("data.csv", CSV)]);
+        assert_eq!(
+            read_all(uncompressed(file_with(&single), &csv, MAX_BYTES)),
+            CSV
+        );
+        let several = zip_with(&[("README", "Test"), ("data/list.csv", CSV)]);
+        assert!(uncompressed(file_with(&several), &csv, MAX_BYTES).is_err());
+        let member = source(DataSourceFormat::CSV, Some("data/list.csv"));
+        assert_eq!(
+            read_all(uncompressed(file_with(&several), &member, MAX_BYTES)),
+            CSV
+        );
+        let missing = source(DataSourceFormat::CSV, Some("other.csv"));
+        assert!(uncompressed(file_with(&several), &missing, MAX_BYTES).is_err());
+
+        // An XLSX file is a zip archive, and stays as it is
+        let excel = source(DataSourceFormat::EXCEL, None);
+        let mut data = vec![];
+        uncompressed(file_with(&single), &excel, MAX_BYTES)
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, single);
+    }
+
+    #[test]
+    fn test_uncompressed_limit() {
+        let csv = source(DataSourceFormat::CSV, None);
+        let max_bytes = CSV.len() as u64;
+        let mut gz = GzEncoder::new(vec![], flate2::Compression::default());
+        gz.write_all(CSV.as_bytes()).unwrap();
+        let gz = gz.finish().unwrap();
+        assert_eq!(read_all(uncompressed(file_with(&gz), &csv, max_bytes)), CSV);
+        assert!(matches!(
+            uncompressed(file_with(&gz), &csv, max_bytes - 1),
+            Err(GulpError::FetchTooLarge(_, limit)) if limit == max_bytes - 1
+        ));
+
+        // A small archive of a large file
+        let mut gz = GzEncoder::new(vec![], flate2::Compression::best());
+        gz.write_all(&vec![b'0'; 10 << 20]).unwrap();
+        let gz = gz.finish().unwrap();
+        assert!(gz.len() < 100_000);
+        assert!(matches!(
+            uncompressed(file_with(&gz), &csv, MAX_BYTES),
+            Err(GulpError::FetchTooLarge(_, _))
+        ));
+
+        let single = zip_with(&[("data.csv", CSV)]);
+        assert!(matches!(
+            uncompressed(file_with(&single), &csv, max_bytes - 1),
+            Err(GulpError::FetchTooLarge(_, _))
+        ));
+        // Uncompressed files are limited by the download
+        assert!(uncompressed(file_with(CSV.as_bytes()), &csv, 1).is_ok());
     }
 }
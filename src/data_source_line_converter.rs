@@ -1,6 +1,11 @@
-use std::io::{self, BufRead, BufReader, Seek};
-use std::sync::Arc;
-use tempfile::tempdir;
+use calamine::Reader;
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::sync::mpsc::{sync_channel, SyncSender};
 
 use crate::app_state::AppState;
 use crate::cell::*;
@@ -9,135 +14,632 @@ use crate::data_source::*;
 use crate::row::Row;
 use crate::{header::*, GulpError};
 
-type LinesReturnType = Vec<String>;
+/// Uses the values of a header row as labels for columns that do not have one yet
+fn label_columns(headers: &mut Vec<HeaderColumn>, names: Vec<String>) {
+    for (num, name) in names.into_iter().enumerate() {
+        while headers.len() <= num {
+            headers.push(string_column());
+        }
+        let name = name.trim();
+        if headers[num].label.is_none() && !name.is_empty() {
+            headers[num].label = Some(name.to_string());
+        }
+    }
+}
+
+/// Finds a worksheet by name, or by zero-based index; the first one by default
+fn select_sheet(sheet_names: &[String], sheet: &Option<String>) -> Result<String, GulpError> {
+    let sheet = match sheet {
+        Some(sheet) => sheet,
+        None => {
+            return Ok(sheet_names
+                .first()
+                .ok_or("No worksheets in file")?
+                .to_owned())
+        }
+    };
+    if let Some(name) = sheet_names.iter().find(|name| *name == sheet) {
+        return Ok(name.to_owned());
+    }
+    sheet
+        .parse::<usize>()
+        .ok()
+        .and_then(|index| sheet_names.get(index))
+        .cloned()
+        .ok_or_else(|| format!("No worksheet '{sheet}' in file").into())
+}
+
+/// Turns spreadsheet rows (Excel, ODS) into cells, honoring the sheet range
+/// and the "first row is header" option of `header_file`
+fn get_cells_from_sheet<T>(
+    header_file: &FileWithHeader,
+    sheet_rows: impl Iterator<Item = impl AsRef<[T]>>,
+    limit: usize,
+    value_as_string: fn(&T) -> String,
+    value_as_json: fn(&T, &HeaderColumn) -> serde_json::Value,
+) -> CellSet {
+    let sheet_range = header_file.excel_range.to_owned().unwrap_or_default();
+    let mut rows: Vec<_> = vec![];
+    let mut headers = header_file.headers.to_owned();
+    let mut records = sheet_range.rows(sheet_rows);
+    if header_file.first_row_is_header {
+        if let Some(record) = records.next() {
+            let names = sheet_range
+                .columns(record.as_ref())
+                .iter()
+                .map(value_as_string)
+                .collect();
+            label_columns(&mut headers, names);
+        }
+    }
+    for record in records {
+        let record = sheet_range.columns(record.as_ref());
+        while headers.len() < record.len() {
+            headers.push(string_column());
+        }
+        let mut row = Row::new();
+        row.cells = record
+            .iter()
+            .zip(headers.iter())
+            .map(|(value, column)| {
+                let value = value_as_json(value, column);
+                Cell::from_value(&value, column)
+            })
+            .collect();
+        rows.push(row);
+        if rows.len() >= limit {
+            break;
+        }
+    }
+    CellSet {
+        headers,
+        rows,
+        headers_from_file: false,
+    }
+}
 
-/// Trait for converting FileWithHeader via various formats into CellSet
+/// Rows of a source, read one at a time. The headers can grow while reading,
+/// e.g. when a line has more fields than the ones before.
+pub trait RowReader: Iterator<Item = Result<Row, GulpError>> + Send {
+    fn headers(&self) -> &[HeaderColumn];
+
+    /// The headers were given by the file itself (JSONL schema line), so they are not guessed
+    fn headers_from_file(&self) -> bool {
+        false
+    }
+}
+
+/// Rows that are already in memory, for formats that can only be read as a whole
+struct CellSetReader {
+    headers: Vec<HeaderColumn>,
+    rows: std::vec::IntoIter<Row>,
+}
+
+impl CellSetReader {
+    fn new_boxed(cell_set: CellSet) -> Box<dyn RowReader> {
+        Box::new(Self {
+            headers: cell_set.headers,
+            rows: cell_set.rows.into_iter(),
+        })
+    }
+}
+
+impl Iterator for CellSetReader {
+    type Item = Result<Row, GulpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rows.next().map(Ok)
+    }
+}
+
+impl RowReader for CellSetReader {
+    fn headers(&self) -> &[HeaderColumn] {
+        &self.headers
+    }
+}
+
+/// A `String` column without a label
+pub fn string_column() -> HeaderColumn {
+    HeaderColumn {
+        column_type: ColumnType::String,
+        wiki: None,
+        string: None,
+        namespace_id: None,
+        unit: None,
+        label: None,
+        description: None,
+    }
+}
+
+/// Trait for converting FileWithHeader via various formats into rows
 pub trait DataSourceLineConverter {
-    fn get_cells(
+    /// Reads the rows one by one, so a large file never has to be in memory as a whole
+    fn get_row_reader(
         &self,
         header_file: &mut FileWithHeader,
-        limit: Option<usize>,
-    ) -> Result<CellSet, GulpError>;
+    ) -> Result<Box<dyn RowReader>, GulpError>;
 
-    fn get_lines(
+    /// The first `limit` rows, e.g. for a preview
+    fn get_cells(
         &self,
         header_file: &mut FileWithHeader,
-        limit: usize,
-    ) -> Result<LinesReturnType, GulpError> {
-        let file = Arc::get_mut(&mut header_file.file).unwrap();
-        let mut reader = io::BufReader::new(file);
-        let mut lines = vec![];
+        limit: Option<usize>,
+    ) -> Result<CellSet, GulpError> {
+        let mut reader = self.get_row_reader(header_file)?;
+        let rows = reader
+            .by_ref()
+            .take(limit.unwrap_or(usize::MAX))
+            .collect::<Result<Vec<Row>, GulpError>>()?;
+        Ok(CellSet {
+            headers: reader.headers().to_vec(),
+            rows,
+            headers_from_file: reader.headers_from_file(),
+        })
+    }
+}
+
+struct XsvReader {
+    records: csv::StringRecordsIntoIter<BufReader<File>>,
+    headers: Vec<HeaderColumn>,
+}
+
+impl XsvReader {
+    fn new_boxed(
+        separator: u8,
+        header_file: &FileWithHeader,
+    ) -> Result<Box<dyn RowReader>, GulpError> {
+        let mut records = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .delimiter(separator)
+            .from_reader(BufReader::new(header_file.reopen()?))
+            .into_records();
+        let mut headers = header_file.headers.to_owned();
+        if header_file.first_row_is_header {
+            if let Some(record) = records.next() {
+                label_columns(
+                    &mut headers,
+                    record?.iter().map(|s| s.to_string()).collect(),
+                );
+            }
+        }
+        Ok(Box::new(Self { records, headers }))
+    }
+}
+
+impl Iterator for XsvReader {
+    type Item = Result<Row, GulpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = match self.records.next()? {
+            Ok(record) => record,
+            Err(e) => return Some(Err(e.into())),
+        };
+        while self.headers.len() < record.len() {
+            self.headers.push(string_column());
+        }
+        let mut row = Row::new();
+        row.cells = record
+            .iter()
+            .zip(self.headers.iter())
+            .map(|(value, column)| {
+                let value = serde_json::Value::String(value.to_string());
+                Cell::from_value(&value, column)
+            })
+            .collect();
+        Some(Ok(row))
+    }
+}
+
+impl RowReader for XsvReader {
+    fn headers(&self) -> &[HeaderColumn] {
+        &self.headers
+    }
+}
+
+/// JSONL with one array of cells per line, optionally preceded by a header schema line
+struct JsonlReader {
+    lines: io::Lines<BufReader<File>>,
+    pending: Option<String>,
+    headers: Vec<HeaderColumn>,
+    headers_from_file: bool,
+    /// No headers were given, so columns are added as needed
+    add_columns: bool,
+}
+
+impl JsonlReader {
+    fn new_boxed(header_file: &FileWithHeader) -> Result<Box<dyn RowReader>, GulpError> {
+        let mut lines = BufReader::new(header_file.reopen()?).lines();
+        let mut headers = header_file.headers.to_owned();
+        let add_columns = headers.is_empty();
+        let mut headers_from_file = false;
+        let mut pending = lines.next().transpose()?;
+        // An optional first line with the header schema, as written by the JSONL export
+        if let Some(line) = pending.as_ref().filter(|l| l.trim_start().starts_with('{')) {
+            let schema = HeaderSchema::from_name_json("", line)
+                .ok_or("import_jsonl: first line is an object but not a header schema")?;
+            if add_columns {
+                headers = schema.columns;
+                headers_from_file = true;
+            }
+            pending = None;
+        }
+        Ok(Box::new(Self {
+            lines,
+            pending,
+            headers,
+            headers_from_file,
+            add_columns: add_columns && !headers_from_file,
+        }))
+    }
+
+    fn next_line(&mut self) -> Option<Result<String, GulpError>> {
         loop {
-            let mut buffer_string = String::new();
-            match reader.read_line(&mut buffer_string)? {
-                0 => break,
-                _ => lines.push(buffer_string),
+            let line = match self.pending.take() {
+                Some(line) => line,
+                None => match self.lines.next()? {
+                    Ok(line) => line,
+                    Err(e) => return Some(Err(e.into())),
+                },
+            };
+            if !line.trim().is_empty() {
+                return Some(Ok(line));
             }
-            if lines.len() >= limit {
-                break;
+        }
+    }
+}
+
+impl Iterator for JsonlReader {
+    type Item = Result<Row, GulpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = match self.next_line()? {
+            Ok(line) => line,
+            Err(e) => return Some(Err(e)),
+        };
+        let json: serde_json::Value = match serde_json::from_str(&line) {
+            Ok(json) => json,
+            Err(e) => return Some(Err(e.into())),
+        };
+        let array = match json.as_array() {
+            Some(array) => array,
+            None => {
+                return Some(Err(format!(
+                    "import_jsonl: valid JSON but not an array: {line}"
+                )
+                .into()))
             }
+        };
+        while self.add_columns && self.headers.len() < array.len() {
+            self.headers.push(string_column());
         }
-        reader.rewind()?;
-        Ok(lines)
+        let mut row = Row::new();
+        row.cells = array
+            .iter()
+            .zip(self.headers.iter())
+            .map(|(value, column)| Cell::from_value(value, column))
+            .collect();
+        Some(Ok(row))
     }
+}
+
+impl RowReader for JsonlReader {
+    fn headers(&self) -> &[HeaderColumn] {
+        &self.headers
+    }
+
+    fn headers_from_file(&self) -> bool {
+        self.headers_from_file
+    }
+}
 
-    fn get_cells_xsv(
+impl DataSourceLineConverter for DataSourceFormatJSONL {
+    fn get_row_reader(
         &self,
-        separator: u8,
         header_file: &mut FileWithHeader,
-        limit: Option<usize>,
-    ) -> Result<CellSet, GulpError> {
-        let limit = limit.unwrap_or(usize::MAX);
-        let file = Arc::get_mut(&mut header_file.file).unwrap();
-        let reader = BufReader::new(file);
-        let mut rdr = csv::ReaderBuilder::new()
-            .has_headers(false)
-            .delimiter(separator)
-            .from_reader(reader);
-        let mut rows: Vec<_> = vec![];
-        let mut headers = header_file.headers.to_owned();
-        for result in rdr.records() {
-            let record = result?;
-            while headers.len() < record.len() {
-                headers.push(HeaderColumn {
-                    column_type: ColumnType::String,
-                    wiki: None,
-                    string: None,
-                    namespace_id: None,
-                });
+    ) -> Result<Box<dyn RowReader>, GulpError> {
+        JsonlReader::new_boxed(header_file)
+    }
+}
+
+/// A JSON object with its keys in document order (`serde_json::Map` sorts them)
+struct JsonObject(Vec<(String, serde_json::Value)>);
+
+impl<'de> Deserialize<'de> for JsonObject {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(JsonObjectVisitor)
+    }
+}
+
+struct JsonObjectVisitor;
+
+impl<'de> Visitor<'de> for JsonObjectVisitor {
+    type Value = JsonObject;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a JSON object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut entries = vec![];
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(JsonObject(entries))
+    }
+}
+
+type JsonObjectResult = Result<JsonObject, GulpError>;
+
+/// Objects read from a JSON document at a time, before the reading thread waits
+const JSON_CHANNEL_SIZE: usize = 1000;
+
+/// Finds the array at a JSON pointer, skipping everything else, and sends its
+/// objects one by one. The pointer is given as its unescaped reference tokens.
+/// Returns false if there is no such array.
+struct JsonPointerSeed<'a> {
+    tokens: &'a [String],
+    sender: &'a SyncSender<JsonObjectResult>,
+}
+
+impl<'de, 'a> DeserializeSeed<'de> for JsonPointerSeed<'a> {
+    type Value = bool;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        if self.tokens.is_empty() {
+            deserializer.deserialize_seq(self)
+        } else {
+            deserializer.deserialize_any(self)
+        }
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for JsonPointerSeed<'a> {
+    type Value = bool;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.tokens.first() {
+            Some(token) => write!(f, "an object or array containing '{token}'"),
+            None => f.write_str("an array of objects"),
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut found = false;
+        while let Some(key) = map.next_key::<String>()? {
+            if !found && key == self.tokens[0] {
+                found = map.next_value_seed(JsonPointerSeed {
+                    tokens: &self.tokens[1..],
+                    sender: self.sender,
+                })?;
+            } else {
+                map.next_value::<IgnoredAny>()?;
             }
-            let mut row = Row::new();
-            row.cells = record
-                .iter()
-                .zip(headers.iter())
-                .map(|(value, column)| {
-                    let value = serde_json::Value::String(value.to_string());
-                    Cell::from_value(&value, column)
-                })
-                .collect();
-            rows.push(row);
-            if rows.len() >= limit {
+        }
+        Ok(found)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        if self.tokens.is_empty() {
+            while let Some(object) = seq.next_element::<JsonObject>()? {
+                if self.sender.send(Ok(object)).is_err() {
+                    // Nobody is reading anymore
+                    return Err(serde::de::Error::custom("stopped reading"));
+                }
+            }
+            return Ok(true);
+        }
+        let index = self.tokens[0].parse::<usize>().ok();
+        let mut found = false;
+        let mut num = 0;
+        loop {
+            if Some(num) == index {
+                let seed = JsonPointerSeed {
+                    tokens: &self.tokens[1..],
+                    sender: self.sender,
+                };
+                match seq.next_element_seed(seed)? {
+                    Some(value) => found = value,
+                    None => break,
+                }
+            } else if seq.next_element::<IgnoredAny>()?.is_none() {
                 break;
             }
+            num += 1;
         }
-        Ok(CellSet { headers, rows })
+        Ok(found)
     }
 }
 
-impl DataSourceLineConverter for DataSourceFormatJSONL {
-    fn get_cells(
+/// Reads the objects of the array at `pointer` in a separate thread, so the
+/// document never has to be in memory as a whole
+fn read_json_array(file: File, pointer: String) -> std::sync::mpsc::IntoIter<JsonObjectResult> {
+    let (sender, receiver) = sync_channel(JSON_CHANNEL_SIZE);
+    std::thread::spawn(move || {
+        // "/a~1b/c" => ["a/b","c"]
+        let tokens: Vec<String> = pointer
+            .split('/')
+            .skip(1)
+            .map(|token| token.replace("~1", "/").replace("~0", "~"))
+            .collect();
+        let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(file));
+        let seed = JsonPointerSeed {
+            tokens: &tokens,
+            sender: &sender,
+        };
+        let error: GulpError = match seed.deserialize(&mut deserializer) {
+            Ok(true) => return,
+            Ok(false) => format!("No array at JSON pointer '{pointer}'").into(),
+            Err(e) => e.into(),
+        };
+        let _ = sender.send(Err(error)); // Fails if nobody is reading anymore
+    });
+    receiver.into_iter()
+}
+
+/// Guesses the column type from a nested JSON value, like `{"lat":..,"lon":..}`
+fn json_column_type(value: &serde_json::Value) -> ColumnType {
+    match value.as_object() {
+        Some(o) if o.contains_key("lat") && o.contains_key("lon") => ColumnType::Location,
+        Some(o) if o.contains_key("title") => ColumnType::WikiPage,
+        _ => ColumnType::String,
+    }
+}
+
+/// Turns JSON objects into rows. Object keys are matched to column labels; keys
+/// without a column take the first column without a label, or get a new one,
+/// in the order they are first seen.
+struct ObjectReader {
+    objects: Box<dyn Iterator<Item = JsonObjectResult> + Send>,
+    headers: Vec<HeaderColumn>,
+    keys: Vec<Option<String>>,
+    /// New columns for keys known before their first value; that value sets the column type
+    untyped: HashSet<usize>,
+}
+
+impl ObjectReader {
+    /// `keys` that are known in advance, like the variables of SPARQL results,
+    /// get their columns before the first object is read, in that order.
+    fn new_boxed(
+        header_file: &FileWithHeader,
+        keys: &[String],
+        objects: Box<dyn Iterator<Item = JsonObjectResult> + Send>,
+    ) -> Box<dyn RowReader> {
+        let headers = header_file.headers.to_owned();
+        let mut reader = Self {
+            objects,
+            keys: headers.iter().map(|h| h.label.to_owned()).collect(),
+            headers,
+            untyped: HashSet::new(),
+        };
+        for key in keys {
+            if reader.column_for_key(key).is_none() {
+                if let Some(num) = reader.add_key(key, &serde_json::Value::Null) {
+                    reader.untyped.insert(num);
+                }
+            }
+        }
+        Box::new(reader)
+    }
+
+    fn column_for_key(&self, key: &str) -> Option<usize> {
+        self.keys.iter().position(|k| k.as_deref() == Some(key))
+    }
+
+    /// Returns the number of the new column, if one was added
+    fn add_key(&mut self, key: &str, value: &serde_json::Value) -> Option<usize> {
+        if let Some(num) = self.keys.iter().position(|k| k.is_none()) {
+            self.keys[num] = Some(key.to_owned());
+            return None;
+        }
+        self.keys.push(Some(key.to_owned()));
+        self.headers.push(HeaderColumn {
+            column_type: json_column_type(value),
+            label: Some(key.to_owned()),
+            ..string_column()
+        });
+        Some(self.headers.len() - 1)
+    }
+}
+
+impl Iterator for ObjectReader {
+    type Item = Result<Row, GulpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let object = match self.objects.next()? {
+            Ok(object) => object,
+            Err(e) => return Some(Err(e)),
+        };
+        for (key, value) in &object.0 {
+            if value.is_null() {
+                continue;
+            }
+            match self.column_for_key(key) {
+                Some(num) => {
+                    if self.untyped.remove(&num) {
+                        self.headers[num].column_type = json_column_type(value);
+                    }
+                }
+                None => {
+                    self.add_key(key, value);
+                }
+            }
+        }
+        let mut row = Row::new();
+        row.cells = vec![None; self.headers.len()];
+        for (key, value) in object.0 {
+            let num = match self.column_for_key(&key) {
+                Some(num) => num,
+                None => continue,
+            };
+            let column = &self.headers[num];
+            let value = match (&column.column_type, value) {
+                (ColumnType::String, serde_json::Value::String(s)) => json!(s),
+                (ColumnType::String, serde_json::Value::Null) => json!(null),
+                (ColumnType::String, value) => json!(value.to_string()),
+                (_, value) => value,
+            };
+            row.cells[num] = Cell::from_value(&value, column);
+        }
+        Some(Ok(row))
+    }
+}
+
+impl RowReader for ObjectReader {
+    fn headers(&self) -> &[HeaderColumn] {
+        &self.headers
+    }
+}
+
+impl DataSourceLineConverter for DataSourceFormatJSONLObjects {
+    fn get_row_reader(
         &self,
         header_file: &mut FileWithHeader,
-        limit: Option<usize>,
-    ) -> Result<CellSet, GulpError> {
-        let limit = limit.unwrap_or(usize::MAX);
-        let mut headers = header_file.headers.to_owned();
-        let lines = self.get_lines(header_file, limit)?;
-        if headers.is_empty() {
-            headers = self.guess_headers(&lines);
-        }
-        let mut rows: Vec<_> = vec![];
-        for line in &lines {
-            let json: serde_json::Value = serde_json::from_str(line)?;
-            let array = json
-                .as_array()
-                .ok_or("import_jsonl: valid JSON but not an array: {row}")?;
-            let mut row = Row::new();
-            row.cells = array
-                .iter()
-                .zip(headers.iter())
-                .map(|(value, column)| Cell::from_value(value, column))
-                .collect();
-            rows.push(row);
-        }
-        Ok(CellSet {
-            headers: headers.to_owned(),
-            rows,
-        })
+    ) -> Result<Box<dyn RowReader>, GulpError> {
+        let objects = BufReader::new(header_file.reopen()?)
+            .lines()
+            .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+            .map(|line| Ok(serde_json::from_str::<JsonObject>(&line?)?));
+        Ok(ObjectReader::new_boxed(header_file, &[], Box::new(objects)))
+    }
+}
+
+impl DataSourceLineConverter for DataSourceFormatJSON {
+    fn get_row_reader(
+        &self,
+        header_file: &mut FileWithHeader,
+    ) -> Result<Box<dyn RowReader>, GulpError> {
+        let pointer = header_file.json_pointer.to_owned().unwrap_or_default();
+        let objects = read_json_array(header_file.reopen()?, pointer);
+        Ok(ObjectReader::new_boxed(header_file, &[], Box::new(objects)))
     }
 }
 
 impl DataSourceLineConverter for DataSourceFormatCSV {
-    fn get_cells(
+    fn get_row_reader(
         &self,
         header_file: &mut FileWithHeader,
-        limit: Option<usize>,
-    ) -> Result<CellSet, GulpError> {
-        self.get_cells_xsv(b',', header_file, limit)
+    ) -> Result<Box<dyn RowReader>, GulpError> {
+        XsvReader::new_boxed(b',', header_file)
     }
 }
 
 impl DataSourceLineConverter for DataSourceFormatTSV {
-    fn get_cells(
+    fn get_row_reader(
         &self,
         header_file: &mut FileWithHeader,
-        limit: Option<usize>,
-    ) -> Result<CellSet, GulpError> {
-        self.get_cells_xsv(b'\t', header_file, limit)
+    ) -> Result<Box<dyn RowReader>, GulpError> {
+        XsvReader::new_boxed(b'\t', header_file)
     }
 }
 
 impl DataSourceLineConverter for DataSourceFormatPagePile {
+    fn get_row_reader(
+        &self,
+        header_file: &mut FileWithHeader,
+    ) -> Result<Box<dyn RowReader>, GulpError> {
+        Ok(CellSetReader::new_boxed(self.get_cells(header_file, None)?))
+    }
+
     fn get_cells(
         &self,
         header_file: &mut FileWithHeader,
@@ -150,6 +652,9 @@ impl DataSourceLineConverter for DataSourceFormatPagePile {
             wiki: Some(wiki),
             string: None,
             namespace_id: None,
+            unit: None,
+            label: None,
+            description: None,
         }];
         let wiki = headers[0]
             .wiki
@@ -167,72 +672,829 @@ impl DataSourceLineConverter for DataSourceFormatPagePile {
             .map(|page| vec![Some(Cell::WikiPage(page))])
             .map(Row::from_cells)
             .collect();
-        Ok(CellSet { headers, rows })
+        Ok(CellSet {
+            headers,
+            rows,
+            headers_from_file: false,
+        })
+    }
+}
+
+/// Entity URIs of Wikidata, e.g. "http://www.wikidata.org/entity/Q42"
+const WIKIDATA_ENTITY_PREFIX: &str = "http://www.wikidata.org/entity/";
+
+/// Datatype of geographic literals, e.g. "Point(13.4 52.5)"
+const WKT_LITERAL: &str = "http://www.opengis.net/ont/geosparql#wktLiteral";
+
+#[derive(Deserialize)]
+struct SparqlResultsHead {
+    head: SparqlVars,
+}
+
+#[derive(Deserialize)]
+struct SparqlVars {
+    #[serde(default)]
+    vars: Vec<String>,
+}
+
+impl DataSourceFormatSparqlJSON {
+    /// The result variables, in the order of the query
+    fn get_vars(header_file: &FileWithHeader) -> Result<Vec<String>, GulpError> {
+        let reader = BufReader::new(header_file.reopen()?);
+        let results: SparqlResultsHead = serde_json::from_reader(reader)?;
+        Ok(results.head.vars)
+    }
+
+    /// Turns a binding of variables to RDF terms into an object with a value per variable.
+    /// Wikidata entities become wiki pages, and points become locations.
+    fn binding_as_object(vars: &[String], binding: JsonObject) -> JsonObject {
+        let mut terms = binding.0;
+        let entries = vars
+            .iter()
+            .filter_map(|var| {
+                let pos = terms.iter().position(|(name, _)| name == var)?;
+                let (name, term) = terms.remove(pos);
+                Some((name, Self::term_as_value(&term)))
+            })
+            .collect();
+        JsonObject(entries)
+    }
+
+    fn term_as_value(term: &serde_json::Value) -> serde_json::Value {
+        let value = term["value"].as_str().unwrap_or_default();
+        let is_uri = term["type"].as_str() == Some("uri");
+        if let Some(page) = Self::wikidata_page(value).filter(|_| is_uri) {
+            return page;
+        }
+        if term["datatype"].as_str() == Some(WKT_LITERAL) {
+            if let Some(location) = Self::point_as_location(value) {
+                return location;
+            }
+        }
+        json!(value)
+    }
+
+    /// An entity URI as a page on Wikidata
+    fn wikidata_page(uri: &str) -> Option<serde_json::Value> {
+        let id = uri.strip_prefix(WIKIDATA_ENTITY_PREFIX)?;
+        let namespace_id = match id.chars().next()? {
+            'Q' => 0,
+            'P' => 120,
+            'L' => 146,
+            _ => return None,
+        };
+        if !RE_WIKIDATA.is_match(id) {
+            return None;
+        }
+        Some(json!({"title":id,"namespace_id":namespace_id,"wiki":"wikidatawiki"}))
+    }
+
+    /// "Point(lon lat)", possibly preceded by the URI of another globe
+    fn point_as_location(wkt: &str) -> Option<serde_json::Value> {
+        let point = wkt.split("Point(").nth(1)?.strip_suffix(')')?;
+        let mut coordinates = point.split_whitespace();
+        let lon: f64 = coordinates.next()?.parse().ok()?;
+        let lat: f64 = coordinates.next()?.parse().ok()?;
+        Some(json!({"lat":lat,"lon":lon}))
+    }
+}
+
+impl DataSourceLineConverter for DataSourceFormatSparqlJSON {
+    fn get_row_reader(
+        &self,
+        header_file: &mut FileWithHeader,
+    ) -> Result<Box<dyn RowReader>, GulpError> {
+        let vars = Self::get_vars(header_file)?;
+        let binding_vars = vars.to_owned();
+        let objects = read_json_array(header_file.reopen()?, "/results/bindings".to_string())
+            .map(move |binding| Ok(Self::binding_as_object(&binding_vars, binding?)));
+        Ok(ObjectReader::new_boxed(
+            header_file,
+            &vars,
+            Box::new(objects),
+        ))
+    }
+}
+
+/// Keeps the type of numbers and booleans for typed columns; `String` columns get the
+/// same text as before typed columns existed, so re-imports do not create duplicate rows.
+fn sheet_value_as_json(value: &calamine::Data, column: &HeaderColumn) -> serde_json::Value {
+    if column.column_type == ColumnType::String {
+        return serde_json::Value::String(sheet_value_as_string(value));
+    }
+    match value {
+        calamine::Data::Int(i) => json!(i),
+        calamine::Data::Float(f) => json!(f),
+        calamine::Data::Bool(b) => json!(b),
+        calamine::Data::Error(_) | calamine::Data::Empty => json!(null),
+        other => json!(sheet_value_as_string(other)),
+    }
+}
+
+fn sheet_value_as_string(value: &calamine::Data) -> String {
+    match value {
+        calamine::Data::Int(i) => format!("{i}"),
+        calamine::Data::Float(f) => format!("{f}"),
+        calamine::Data::String(s) => s.to_owned(),
+        calamine::Data::Bool(b) => format!("{}", *b as u8),
+        calamine::Data::DateTime(dt) => match dt.as_datetime() {
+            Some(datetime) if !dt.is_duration() => sheet_datetime_as_string(&datetime),
+            _ => format!("{}", dt.as_f64()),
+        },
+        calamine::Data::DateTimeIso(s) => s.to_owned(),
+        calamine::Data::DurationIso(s) => s.to_owned(),
+        calamine::Data::Error(_) => "".into(),
+        calamine::Data::Empty => "".into(),
+    }
+}
+
+/// ISO 8601, like "2001-12-31", or "2001-12-31T12:34:56Z" if there is a time of day
+fn sheet_datetime_as_string(datetime: &chrono::NaiveDateTime) -> String {
+    if datetime.time() == chrono::NaiveTime::MIN {
+        datetime.format("%Y-%m-%d").to_string()
+    } else {
+        datetime.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+    }
+}
+
+/// `calamine` ranges start at the first used cell; row and column numbers
+/// of the sheet range refer to the whole sheet. Without a sheet range,
+/// reading starts at the first used cell.
+fn get_cells_from_range(
+    header_file: &FileWithHeader,
+    range: &calamine::Range<calamine::Data>,
+    limit: usize,
+) -> CellSet {
+    let (first_row, first_column) = match header_file.excel_range {
+        Some(_) => (0, 0),
+        None => range.start().unwrap_or_default(),
+    };
+    let (rows, columns) = match range.end() {
+        Some((end_row, end_column)) => (end_row + 1, end_column + 1),
+        None => (0, 0),
+    };
+    let sheet_rows = (first_row..rows).map(|row| {
+        (first_column..columns)
+            .map(|column| {
+                range
+                    .get_value((row, column))
+                    .cloned()
+                    .unwrap_or(calamine::Data::Empty)
+            })
+            .collect::<Vec<_>>()
+    });
+    get_cells_from_sheet(
+        header_file,
+        sheet_rows,
+        limit,
+        sheet_value_as_string,
+        sheet_value_as_json,
+    )
+}
+
+impl DataSourceFormatExcel {
+    /// Detects the format (XLSX, XLS, XLSB) from the content. `calamine` needs a reader
+    /// that can be cloned for that, so the file is read into memory.
+    fn open_workbook(
+        header_file: &FileWithHeader,
+    ) -> Result<calamine::Sheets<io::Cursor<Vec<u8>>>, GulpError> {
+        let mut data = vec![];
+        header_file.reopen()?.read_to_end(&mut data)?;
+        let workbook = calamine::open_workbook_auto_from_rs(io::Cursor::new(data))
+            .map_err(|e| e.to_string())?;
+        Ok(workbook)
+    }
+
+    pub fn get_sheet_names(
+        &self,
+        header_file: &mut FileWithHeader,
+    ) -> Result<Vec<String>, GulpError> {
+        Ok(Self::open_workbook(header_file)?.sheet_names())
     }
 }
 
 impl DataSourceLineConverter for DataSourceFormatExcel {
+    fn get_row_reader(
+        &self,
+        header_file: &mut FileWithHeader,
+    ) -> Result<Box<dyn RowReader>, GulpError> {
+        Ok(CellSetReader::new_boxed(self.get_cells(header_file, None)?))
+    }
+
     fn get_cells(
         &self,
         header_file: &mut FileWithHeader,
         limit: Option<usize>,
     ) -> Result<CellSet, GulpError> {
         let limit = limit.unwrap_or(usize::MAX);
-        let mut reader =
-            Arc::get_mut(&mut header_file.file).ok_or("Cannot get temporaryfile handle")?;
-
-        let tmpdir = tempdir()?;
-        let file_path = tmpdir.path().join("temporary.xlsx");
-        let mut writer = std::fs::File::create(file_path.clone())?;
-        io::copy(&mut reader, &mut writer)?;
-        drop(writer);
-        reader.rewind()?;
-
-        let mut workbook = office::Excel::open(file_path).map_err(|e| e.to_string())?;
-        let first_workbook_name = workbook
-            .sheet_names()
-            .map_err(|e| e.to_string())?
-            .first()
-            .ok_or("No worksheets in Excel file")?
-            .to_owned();
+        let mut workbook = Self::open_workbook(header_file)?;
+        let sheet_name = select_sheet(&workbook.sheet_names(), &header_file.excel_sheet)?;
         let range = workbook
-            .worksheet_range(&first_workbook_name)
+            .worksheet_range(&sheet_name)
             .map_err(|e| e.to_string())?;
+        Ok(get_cells_from_range(header_file, &range, limit))
+    }
+}
 
-        let mut rows: Vec<_> = vec![];
-        let mut headers = header_file.headers.to_owned();
-        for record in range.rows() {
-            while headers.len() < record.len() {
-                headers.push(HeaderColumn {
-                    column_type: ColumnType::String,
-                    wiki: None,
-                    string: None,
-                    namespace_id: None,
-                });
+impl DataSourceFormatODS {
+    fn open_workbook(
+        header_file: &FileWithHeader,
+    ) -> Result<calamine::Ods<BufReader<File>>, GulpError> {
+        let file = header_file.reopen()?;
+        let workbook: calamine::Ods<_> = calamine::open_workbook_from_rs(BufReader::new(file))
+            .map_err(|e: calamine::OdsError| e.to_string())?;
+        Ok(workbook)
+    }
+
+    pub fn get_sheet_names(
+        &self,
+        header_file: &mut FileWithHeader,
+    ) -> Result<Vec<String>, GulpError> {
+        Ok(Self::open_workbook(header_file)?.sheet_names())
+    }
+}
+
+impl DataSourceLineConverter for DataSourceFormatODS {
+    fn get_row_reader(
+        &self,
+        header_file: &mut FileWithHeader,
+    ) -> Result<Box<dyn RowReader>, GulpError> {
+        Ok(CellSetReader::new_boxed(self.get_cells(header_file, None)?))
+    }
+
+    fn get_cells(
+        &self,
+        header_file: &mut FileWithHeader,
+        limit: Option<usize>,
+    ) -> Result<CellSet, GulpError> {
+        let limit = limit.unwrap_or(usize::MAX);
+        let excel_sheet = header_file.excel_sheet.to_owned();
+        let range = {
+            let mut workbook = Self::open_workbook(header_file)?;
+            let sheet_name = select_sheet(&workbook.sheet_names(), &excel_sheet)?;
+            workbook
+                .worksheet_range(&sheet_name)
+                .map_err(|e| e.to_string())?
+        };
+        Ok(get_cells_from_range(header_file, &range, limit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Seek, Write};
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_get_cells_xsv_first_row_is_header() {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(b"qid,name\nQ42,Douglas Adams\n").unwrap();
+        file.rewind().unwrap();
+        let mut header_file = FileWithHeader {
+            headers: vec![],
+            first_row_is_header: true,
+            excel_sheet: None,
+            excel_range: None,
+            json_pointer: None,
+            file: Arc::new(file),
+        };
+        let cell_set = DataSourceFormatCSV {}
+            .get_cells(&mut header_file, None)
+            .unwrap();
+        assert_eq!(cell_set.rows.len(), 1);
+        assert_eq!(cell_set.headers[0].label, Some("qid".to_string()));
+        assert_eq!(cell_set.headers[1].label, Some("name".to_string()));
+    }
+
+    #[test]
+    fn test_select_sheet() {
+        let names = vec!["Intro".to_string(), "Data".to_string()];
+        let select = |sheet: Option<&str>| select_sheet(&names, &sheet.map(|s| s.to_string()));
+        assert_eq!(select(None).unwrap(), "Intro");
+        assert_eq!(select(Some("Data")).unwrap(), "Data");
+        assert_eq!(select(Some("1")).unwrap(), "Data");
+        assert!(select(Some("2")).is_err());
+    }
+
+    #[test]
+    fn test_get_cells_from_sheet_with_list_columns() {
+        let header_file = FileWithHeader {
+            headers: vec![
+                string_column(),
+                HeaderColumn {
+                    column_type: ColumnType::Number,
+                    ..string_column()
+                },
+                HeaderColumn {
+                    column_type: ColumnType::Boolean,
+                    ..string_column()
+                },
+            ],
+            first_row_is_header: true,
+            excel_sheet: None,
+            excel_range: None,
+            json_pointer: None,
+            file: Arc::new(tempfile::tempfile().unwrap()),
+        };
+        let sheet_rows = vec![
+            vec![
+                calamine::Data::String("name".into()),
+                calamine::Data::String("height".into()),
+                calamine::Data::String("open".into()),
+            ],
+            vec![
+                calamine::Data::String("Tower".into()),
+                calamine::Data::Float(35.5),
+                calamine::Data::Bool(true),
+            ],
+        ];
+        let cell_set = get_cells_from_sheet(
+            &header_file,
+            sheet_rows.into_iter(),
+            usize::MAX,
+            sheet_value_as_string,
+            sheet_value_as_json,
+        );
+        assert_eq!(cell_set.headers[1].label, Some("height".to_string()));
+        assert_eq!(cell_set.headers[1].column_type, ColumnType::Number);
+        let cells = &cell_set.rows[0].cells;
+        assert!(matches!(&cells[0], Some(Cell::String(s)) if s == "Tower"));
+        assert_eq!(
+            cells[1].as_ref().unwrap().as_json(&cell_set.headers[1]),
+            json!(35.5)
+        );
+        assert!(matches!(&cells[2], Some(Cell::Boolean(true))));
+    }
+
+    #[test]
+    fn test_get_cells_xlsx_not_starting_at_a1() {
+        let mut workbook = rust_xlsxwriter::Workbook::new();
+        let worksheet = workbook.add_worksheet();
+        worksheet.write_string(2, 2, "name").unwrap();
+        worksheet.write_string(2, 3, "height").unwrap();
+        worksheet.write_string(3, 2, "Tower").unwrap();
+        worksheet.write_number(3, 3, 35.5).unwrap();
+        worksheet.write_string(4, 2, "Hill").unwrap();
+        worksheet.write_number(4, 3, 120).unwrap();
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&workbook.save_to_buffer().unwrap()).unwrap();
+        let mut header_file = FileWithHeader {
+            headers: vec![],
+            first_row_is_header: true,
+            excel_sheet: None,
+            excel_range: None,
+            json_pointer: None,
+            file: Arc::new(file),
+        };
+        let cell_set = DataSourceFormatExcel {}
+            .get_cells(&mut header_file, None)
+            .unwrap();
+        assert_eq!(cell_set.headers.len(), 2);
+        assert_eq!(cell_set.headers[0].label, Some("name".to_string()));
+        assert_eq!(cell_set.rows.len(), 2);
+        assert!(matches!(&cell_set.rows[1].cells[0], Some(Cell::String(s)) if s == "Hill"));
+
+        // A sheet range refers to the whole sheet, not to the used part
+        header_file.first_row_is_header = false;
+        header_file.excel_range = SheetRange::new("D4:D4");
+        let cell_set = DataSourceFormatExcel {}
+            .get_cells(&mut header_file, None)
+            .unwrap();
+        assert_eq!(cell_set.rows.len(), 1);
+        assert_eq!(cell_set.rows[0].cells.len(), 1);
+        assert!(matches!(&cell_set.rows[0].cells[0], Some(Cell::String(s)) if s == "35.5"));
+    }
+
+    #[tokio::test]
+    async fn test_get_cells_xlsx_dates() {
+        let mut workbook = rust_xlsxwriter::Workbook::new();
+        let worksheet = workbook.add_worksheet();
+        let format = rust_xlsxwriter::Format::new().set_num_format("yyyy-mm-dd hh:mm");
+        let date = rust_xlsxwriter::ExcelDateTime::from_ymd(2023, 7, 15).unwrap();
+        let datetime = rust_xlsxwriter::ExcelDateTime::from_ymd(1999, 12, 31)
+            .unwrap()
+            .and_hms(12, 30, 0)
+            .unwrap();
+        worksheet.write_string(0, 0, "date").unwrap();
+        worksheet
+            .write_datetime_with_format(1, 0, &date, &format)
+            .unwrap();
+        worksheet
+            .write_datetime_with_format(2, 0, &datetime, &format)
+            .unwrap();
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&workbook.save_to_buffer().unwrap()).unwrap();
+        let mut header_file = FileWithHeader {
+            headers: vec![],
+            first_row_is_header: true,
+            excel_sheet: None,
+            excel_range: None,
+            json_pointer: None,
+            file: Arc::new(file),
+        };
+        let cell_set = DataSourceFormatExcel {}
+            .get_cells(&mut header_file, None)
+            .unwrap();
+        let cells: Vec<Cell> = cell_set
+            .rows
+            .iter()
+            .filter_map(|row| row.cells[0].to_owned())
+            .collect();
+        assert!(matches!(&cells[0], Cell::String(s) if s == "2023-07-15"));
+        assert!(matches!(&cells[1], Cell::String(s) if s == "1999-12-31T12:30:00Z"));
+        let column = cell_set.headers[0].guess(cells).await;
+        assert_eq!(column.column_type, ColumnType::Date);
+
+        // A date column of the list gets dates
+        header_file.headers = vec![column];
+        let cell_set = DataSourceFormatExcel {}
+            .get_cells(&mut header_file, None)
+            .unwrap();
+        match &cell_set.rows[1].cells[0] {
+            Some(Cell::Date(date)) => {
+                assert_eq!(date.time, "+1999-12-31T12:30:00Z");
+                assert_eq!(date.precision, Date::PRECISION_MINUTE);
             }
-            let mut row = Row::new();
-            row.cells = record
-                .iter()
-                .zip(headers.iter())
-                .map(|(value, column)| {
-                    let value: String = match value {
-                        office::DataType::Int(i) => format!("{i}"),
-                        office::DataType::Float(f) => format!("{f}"),
-                        office::DataType::String(s) => s.to_owned(),
-                        office::DataType::Bool(b) => format!("{}", *b as u8),
-                        office::DataType::Error(_) => "".into(),
-                        office::DataType::Empty => "".into(),
-                    };
-                    let value = serde_json::Value::String(value);
-                    Cell::from_value(&value, column)
-                })
-                .collect();
-            rows.push(row);
-            if rows.len() >= limit {
-                break;
+            other => panic!("Not a date: {:?}", other),
+        }
+        assert!(
+            matches!(&cell_set.rows[0].cells[0], Some(Cell::Date(date)) if date.precision == Date::PRECISION_DAY)
+        );
+    }
+
+    #[test]
+    fn test_get_cells_ods_with_shared_file() {
+        let mut header = Header::new();
+        header.schema = HeaderSchema::from_name_json(
+            "Test",
+            r#"{"columns":[{"column_type":"String","label":"name"}]}"#,
+        )
+        .unwrap();
+        let rows = vec![Row::from_cells(vec![Some(Cell::String("Tower".into()))])];
+        let data = crate::export_ods::write_ods(&header, &rows, &[]).unwrap();
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&data).unwrap();
+        let mut header_file = FileWithHeader {
+            headers: vec![],
+            first_row_is_header: true,
+            excel_sheet: None,
+            excel_range: None,
+            json_pointer: None,
+            file: Arc::new(file),
+        };
+        // E.g. a preview holds a handle to the file as well
+        let _shared = header_file.file.clone();
+        let format = DataSourceFormatODS {};
+        assert_eq!(
+            format.get_sheet_names(&mut header_file).unwrap(),
+            vec!["Rows", "Metadata"]
+        );
+        for _ in 0..2 {
+            let cell_set = format.get_cells(&mut header_file, None).unwrap();
+            assert_eq!(cell_set.headers[0].label, Some("name".to_string()));
+            assert_eq!(cell_set.rows.len(), 1);
+            assert!(matches!(&cell_set.rows[0].cells[0], Some(Cell::String(s)) if s == "Tower"));
+        }
+    }
+
+    #[test]
+    fn test_get_cells_from_json_objects() {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(
+            br#"{"name":"Berlin","coord":{"lat":52.5,"lon":13.4},"pop":3800000}
+{"page":{"title":"Hamburg","wiki":"dewiki"},"name":"Hamburg","coord":null}
+"#,
+        )
+        .unwrap();
+        file.rewind().unwrap();
+        let mut header_file = FileWithHeader {
+            headers: vec![],
+            first_row_is_header: false,
+            excel_sheet: None,
+            excel_range: None,
+            json_pointer: None,
+            file: Arc::new(file),
+        };
+        let cell_set = DataSourceFormatJSONLObjects {}
+            .get_cells(&mut header_file, None)
+            .unwrap();
+        let labels: Vec<_> = cell_set
+            .headers
+            .iter()
+            .map(|h| h.label.to_owned().unwrap())
+            .collect();
+        assert_eq!(labels, vec!["name", "coord", "pop", "page"]);
+        assert_eq!(cell_set.headers[1].column_type, ColumnType::Location);
+        assert_eq!(cell_set.headers[3].column_type, ColumnType::WikiPage);
+        assert!(matches!(&cell_set.rows[0].cells[2], Some(Cell::String(s)) if s == "3800000"));
+        assert!(cell_set.rows[1].cells[1].is_none());
+        match &cell_set.rows[1].cells[3] {
+            Some(Cell::WikiPage(page)) => assert_eq!(page.wiki, Some("dewiki".to_string())),
+            other => panic!("Not a wiki page: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_get_cells_from_json_objects_with_list_columns() {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(
+            br#"{"name":"Berlin","pop":3800000}
+{"pop":1800000,"qid":"Q1055","name":null}
+{"qid":"Q1726","name":"Munich"}
+"#,
+        )
+        .unwrap();
+        file.rewind().unwrap();
+        let column = |label: &str| HeaderColumn {
+            label: Some(label.to_string()),
+            ..string_column()
+        };
+        let mut header_file = FileWithHeader {
+            headers: vec![column("qid"), column("name"), column("pop")],
+            first_row_is_header: false,
+            excel_sheet: None,
+            excel_range: None,
+            json_pointer: None,
+            file: Arc::new(file),
+        };
+        let cell_set = DataSourceFormatJSONLObjects {}
+            .get_cells(&mut header_file, None)
+            .unwrap();
+        assert_eq!(cell_set.headers.len(), 3);
+        let values: Vec<Vec<String>> = cell_set
+            .rows
+            .iter()
+            .map(|row| {
+                row.cells
+                    .iter()
+                    .zip(cell_set.headers.iter())
+                    .map(|(cell, column)| match cell {
+                        Some(cell) => cell.as_string(column),
+                        None => String::new(),
+                    })
+                    .collect()
+            })
+            .collect();
+        assert_eq!(values[0], vec!["", "Berlin", "3800000"]);
+        assert_eq!(values[1], vec!["Q1055", "", "1800000"]);
+        assert_eq!(values[2], vec!["Q1726", "Munich", ""]);
+
+        // Columns without a label are taken by keys in the order they are first seen
+        header_file.headers = vec![string_column(), string_column()];
+        let cell_set = DataSourceFormatJSONLObjects {}
+            .get_cells(&mut header_file, None)
+            .unwrap();
+        assert_eq!(cell_set.headers.len(), 3);
+        assert!(matches!(&cell_set.rows[1].cells[1], Some(Cell::String(s)) if s == "1800000"));
+        assert!(matches!(&cell_set.rows[1].cells[2], Some(Cell::String(s)) if s == "Q1055"));
+    }
+
+    #[test]
+    fn test_get_cells_json_pointer() {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(br#"{"meta":{"count":2},"data":{"items":[{"b":1,"a":2},{"c":3}]}}"#)
+            .unwrap();
+        file.rewind().unwrap();
+        let mut header_file = FileWithHeader {
+            headers: vec![],
+            first_row_is_header: false,
+            excel_sheet: None,
+            excel_range: None,
+            json_pointer: Some("/data/items".to_string()),
+            file: Arc::new(file),
+        };
+        let cell_set = DataSourceFormatJSON {}
+            .get_cells(&mut header_file, None)
+            .unwrap();
+        assert_eq!(cell_set.rows.len(), 2);
+        assert_eq!(cell_set.headers[0].label, Some("b".to_string()));
+        assert_eq!(cell_set.headers[2].label, Some("c".to_string()));
+
+        header_file.json_pointer = Some("/data/missing".to_string());
+        assert!(DataSourceFormatJSON {}
+            .get_cells(&mut header_file, None)
+            .is_err());
+    }
+
+    #[test]
+    fn test_jsonl_round_trip() {
+        let mut header = Header::new();
+        header.schema = HeaderSchema::from_name_json(
+            "Test",
+            r#"{"columns":[{"column_type":"WikiPage","wiki":"enwiki","namespace_id":0},{"column_type":"String"}]}"#,
+        )
+        .unwrap();
+        let page = |title: &str, wiki: &str| {
+            Some(Cell::WikiPage(WikiPage {
+                title: title.to_string(),
+                namespace_id: Some(0),
+                wiki: Some(wiki.to_string()),
+            }))
+        };
+        let rows = [
+            Row::from_cells(vec![page("Douglas Adams", "enwiki"), None]),
+            Row::from_cells(vec![
+                page("Berlin", "dewiki"),
+                Some(Cell::String("Q42".to_string())),
+            ]),
+        ];
+        let mut jsonl = serde_json::to_string(&header.schema).unwrap() + "\n";
+        for row in &rows {
+            jsonl += &format!("{}\n", row.as_json(&header)["c"]);
+        }
+
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(jsonl.as_bytes()).unwrap();
+        file.rewind().unwrap();
+        let mut header_file = FileWithHeader {
+            headers: vec![],
+            first_row_is_header: false,
+            excel_sheet: None,
+            excel_range: None,
+            json_pointer: None,
+            file: Arc::new(file),
+        };
+        let cell_set = DataSourceFormatJSONL {}
+            .get_cells(&mut header_file, None)
+            .unwrap();
+        assert!(cell_set.headers_from_file);
+        assert_eq!(json!(cell_set.headers), json!(header.schema.columns));
+        let mut imported = Header::new();
+        imported.schema.columns = cell_set.headers;
+        for (row, original) in cell_set.rows.iter().zip(rows.iter()) {
+            assert_eq!(row.as_json(&imported)["c"], original.as_json(&header)["c"]);
+        }
+        assert_eq!(cell_set.rows.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_sparql_from_local_endpoint() {
+        use crate::data_source_as_file::{sparql_query_url, SPARQL_RESULTS_JSON};
+        use crate::fetcher::{Fetcher, FetcherConfig, HttpValidators, UrlFetch};
+        use axum::extract::Query;
+        use axum::http::{header, HeaderMap};
+        use std::collections::HashMap;
+
+        const QUERY: &str = "SELECT ?item ?label ?coord { ?item wdt:P625 ?coord }";
+        const RESULTS: &str = r#"{"head":{"vars":["item","label","coord"]},"results":{"bindings":[
+            {"coord":{"datatype":"http://www.opengis.net/ont/geosparql#wktLiteral","type":"literal","value":"Point(13.4 52.5)"},
+             "item":{"type":"uri","value":"http://www.wikidata.org/entity/Q64"},
+             "label":{"xml:lang":"en","type":"literal","value":"Berlin"}},
+            {"item":{"type":"uri","value":"http://www.wikidata.org/entity/P625"},
+             "label":{"type":"literal","value":"coordinate location"}}
+        ]}}"#;
+
+        // A stand-in for a SPARQL endpoint, which only answers the expected request
+        async fn endpoint(
+            Query(params): Query<HashMap<String, String>>,
+            headers: HeaderMap,
+        ) -> (axum::http::StatusCode, &'static str) {
+            let accept = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok());
+            if params.get("query").map(|q| q.as_str()) != Some(QUERY)
+                || accept != Some(SPARQL_RESULTS_JSON)
+            {
+                return (axum::http::StatusCode::BAD_REQUEST, "");
+            }
+            (axum::http::StatusCode::OK, RESULTS)
+        }
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let router = axum::Router::new().route("/sparql", axum::routing::get(endpoint));
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(router.into_make_service());
+        tokio::spawn(server);
+
+        let config = FetcherConfig::from_config(&json!({"allow_private_addresses":true}));
+        let fetcher = Fetcher::new(config).unwrap();
+        let url = sparql_query_url(&format!("http://{address}/sparql"), QUERY).unwrap();
+        let dir = tempdir().unwrap();
+        let accept = Some(SPARQL_RESULTS_JSON);
+        let fetched = fetcher
+            .fetch_to_file(&url, accept, &HttpValidators::default(), dir.path())
+            .await
+            .unwrap();
+        let file = match fetched {
+            UrlFetch::Downloaded { file, .. } => file.reopen().unwrap(),
+            UrlFetch::NotModified => panic!("Nothing downloaded"),
+        };
+
+        let mut header_file = FileWithHeader {
+            headers: vec![],
+            first_row_is_header: false,
+            excel_sheet: None,
+            excel_range: None,
+            json_pointer: None,
+            file: Arc::new(file),
+        };
+        let cell_set = DataSourceFormatSparqlJSON {}
+            .get_cells(&mut header_file, None)
+            .unwrap();
+        let labels: Vec<_> = cell_set
+            .headers
+            .iter()
+            .map(|h| h.label.to_owned().unwrap())
+            .collect();
+        assert_eq!(labels, vec!["item", "label", "coord"]);
+        assert_eq!(cell_set.headers[0].column_type, ColumnType::WikiPage);
+        assert_eq!(cell_set.headers[1].column_type, ColumnType::String);
+        assert_eq!(cell_set.headers[2].column_type, ColumnType::Location);
+        match &cell_set.rows[0].cells[0] {
+            Some(Cell::WikiPage(page)) => {
+                assert_eq!(page.title, "Q64");
+                assert_eq!(page.namespace_id, Some(0));
+                assert_eq!(page.wiki, Some("wikidatawiki".to_string()));
+            }
+            other => panic!("Not a wiki page: {:?}", other),
+        }
+        match &cell_set.rows[0].cells[2] {
+            Some(Cell::Location(location)) => {
+                assert_eq!((location.lat, location.lon), (52.5, 13.4));
             }
+            other => panic!("Not a location: {:?}", other),
+        }
+        match &cell_set.rows[1].cells[0] {
+            Some(Cell::WikiPage(page)) => assert_eq!(page.namespace_id, Some(120)),
+            other => panic!("Not a wiki page: {:?}", other),
+        }
+        assert!(cell_set.rows[1].cells[2].is_none());
+    }
+
+    #[test]
+    fn test_sparql_vars_give_columns() {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(
+            br#"{"head":{"vars":["item","label","coord"]},"results":{"bindings":[
+            {"coord":{"datatype":"http://www.opengis.net/ont/geosparql#wktLiteral","type":"literal","value":"Point(13.4 52.5)"}},
+            {"label":{"type":"literal","value":"Berlin"},"item":{"type":"uri","value":"http://www.wikidata.org/entity/Q64"}}
+        ]}}"#,
+        )
+        .unwrap();
+        let mut header_file = FileWithHeader {
+            headers: vec![],
+            first_row_is_header: false,
+            excel_sheet: None,
+            excel_range: None,
+            json_pointer: None,
+            file: Arc::new(file),
+        };
+        let cell_set = DataSourceFormatSparqlJSON {}
+            .get_cells(&mut header_file, None)
+            .unwrap();
+        let labels: Vec<_> = cell_set
+            .headers
+            .iter()
+            .map(|h| h.label.to_owned().unwrap())
+            .collect();
+        assert_eq!(labels, vec!["item", "label", "coord"]);
+        assert_eq!(cell_set.headers[0].column_type, ColumnType::WikiPage);
+        assert_eq!(cell_set.headers[2].column_type, ColumnType::Location);
+        assert!(cell_set.rows[0].cells[0].is_none());
+        assert!(matches!(
+            &cell_set.rows[0].cells[2],
+            Some(Cell::Location(_))
+        ));
+        assert!(
+            matches!(&cell_set.rows[1].cells[0], Some(Cell::WikiPage(page)) if page.title == "Q64")
+        );
+        assert!(matches!(&cell_set.rows[1].cells[1], Some(Cell::String(s)) if s == "Berlin"));
+    }
+
+    #[test]
+    fn test_row_reader() {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(b"[\"a\"]\n[\"b\",\"c\"]\n").unwrap();
+        let mut header_file = FileWithHeader {
+            headers: vec![],
+            first_row_is_header: false,
+            excel_sheet: None,
+            excel_range: None,
+            json_pointer: None,
+            file: Arc::new(file),
+        };
+        let mut reader = DataSourceFormatJSONL {}
+            .get_row_reader(&mut header_file)
+            .unwrap();
+        assert_eq!(reader.next().unwrap().unwrap().cells.len(), 1);
+        assert_eq!(reader.headers().len(), 1);
+        assert_eq!(reader.next().unwrap().unwrap().cells.len(), 2);
+        assert_eq!(reader.headers().len(), 2);
+        assert!(reader.next().is_none());
+
+        // Only the rows needed are read from a large JSON array
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(b"[").unwrap();
+        for num in 0..10_000 {
+            file.write_all(format!("{{\"n\":{num}}},").as_bytes())
+                .unwrap();
         }
-        tmpdir.close()?; // Cleanup
-        Ok(CellSet { headers, rows })
+        file.write_all(b"{}]").unwrap();
+        header_file.file = Arc::new(file);
+        let cell_set = DataSourceFormatJSON {}
+            .get_cells(&mut header_file, Some(3))
+            .unwrap();
+        assert_eq!(cell_set.rows.len(), 3);
     }
 }
@@ -0,0 +1,188 @@
+use crate::header::DbId;
+use crate::row::Row;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct CellChange {
+    pub column: usize,
+    pub before: Value,
+    pub after: Value,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RowDiff {
+    pub row_num: DbId,
+    pub user_id: DbId,
+    pub cells: Vec<CellChange>,
+}
+
+/// Changes between two revisions of a list
+#[derive(Clone, Debug, Serialize, Default)]
+pub struct RevisionDiff {
+    pub from: DbId,
+    pub to: DbId,
+    pub added: Vec<RowDiff>,
+    pub removed: Vec<RowDiff>,
+    pub modified: Vec<RowDiff>,
+}
+
+impl RevisionDiff {
+    /// Compares the rows of two revisions by their stored JSON, so header changes
+    /// between the revisions do not matter. `deleted_by` maps the `row_num` of
+    /// deleted rows to the user who deleted them.
+    pub fn new(
+        from: DbId,
+        to: DbId,
+        rows_from: &[Row],
+        rows_to: &[Row],
+        deleted_by: &HashMap<DbId, DbId>,
+    ) -> Self {
+        let rows_from: BTreeMap<DbId, &Row> =
+            rows_from.iter().map(|row| (row.row_num, row)).collect();
+        let rows_to: BTreeMap<DbId, &Row> = rows_to.iter().map(|row| (row.row_num, row)).collect();
+        let mut ret = Self {
+            from,
+            to,
+            ..Default::default()
+        };
+        for (row_num, row) in &rows_to {
+            match rows_from.get(row_num) {
+                Some(old_row) if old_row.json == row.json => {}
+                Some(old_row) => ret.modified.push(RowDiff {
+                    row_num: *row_num,
+                    user_id: row.user_id,
+                    cells: Self::changed_cells(&old_row.json, &row.json),
+                }),
+                None => ret.added.push(RowDiff {
+                    row_num: *row_num,
+                    user_id: row.user_id,
+                    cells: Self::changed_cells("[]", &row.json),
+                }),
+            }
+        }
+        for (row_num, old_row) in &rows_from {
+            if !rows_to.contains_key(row_num) {
+                ret.removed.push(RowDiff {
+                    row_num: *row_num,
+                    user_id: deleted_by.get(row_num).cloned().unwrap_or(0),
+                    cells: Self::changed_cells(&old_row.json, "[]"),
+                });
+            }
+        }
+        ret
+    }
+
+    fn json_to_cells(json: &str) -> Vec<Value> {
+        match serde_json::from_str::<Value>(json) {
+            Ok(Value::Array(cells)) => cells,
+            _ => vec![],
+        }
+    }
+
+    fn changed_cells(before: &str, after: &str) -> Vec<CellChange> {
+        let before = Self::json_to_cells(before);
+        let after = Self::json_to_cells(after);
+        (0..before.len().max(after.len()))
+            .map(|column| CellChange {
+                column,
+                before: before.get(column).cloned().unwrap_or(json!(null)),
+                after: after.get(column).cloned().unwrap_or(json!(null)),
+            })
+            .filter(|change| change.before != change.after)
+            .collect()
+    }
+
+    pub fn user_ids(&self) -> Vec<DbId> {
+        let mut ret: Vec<DbId> = self
+            .added
+            .iter()
+            .chain(self.removed.iter())
+            .chain(self.modified.iter())
+            .map(|row| row.user_id)
+            .collect();
+        ret.sort();
+        ret.dedup();
+        ret
+    }
+
+    fn value_as_string(value: &Value) -> String {
+        match value {
+            Value::Null => String::new(),
+            Value::String(s) => s.to_owned(),
+            other => other.to_string(),
+        }
+    }
+
+    /// One line per changed cell
+    pub fn as_records(&self, users: &HashMap<DbId, String>) -> Vec<Vec<String>> {
+        let mut ret = vec![vec![
+            "change".to_string(),
+            "row".to_string(),
+            "user".to_string(),
+            "column".to_string(),
+            "before".to_string(),
+            "after".to_string(),
+        ]];
+        let groups = [
+            ("added", &self.added),
+            ("removed", &self.removed),
+            ("modified", &self.modified),
+        ];
+        for (change, rows) in groups {
+            for row in rows {
+                let user = users.get(&row.user_id).cloned().unwrap_or_default();
+                for cell in &row.cells {
+                    ret.push(vec![
+                        change.to_string(),
+                        format!("{}", row.row_num),
+                        user.to_owned(),
+                        format!("{}", cell.column),
+                        Self::value_as_string(&cell.before),
+                        Self::value_as_string(&cell.after),
+                    ]);
+                }
+            }
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(row_num: DbId, user_id: DbId, json: &str) -> Row {
+        let mut row = Row::new();
+        row.row_num = row_num;
+        row.user_id = user_id;
+        row.json = json.to_string();
+        row
+    }
+
+    #[test]
+    fn test_new() {
+        let rows_from = vec![row(1, 1, r#"["a","b"]"#), row(2, 1, r#"["c","d"]"#)];
+        let rows_to = vec![row(1, 2, r#"["a","x"]"#), row(3, 3, r#"["e",null]"#)];
+        let deleted_by = HashMap::from([(2, 4)]);
+        let diff = RevisionDiff::new(1, 2, &rows_from, &rows_to, &deleted_by);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].row_num, 3);
+        assert_eq!(diff.added[0].cells.len(), 1);
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].user_id, 4);
+        assert_eq!(diff.removed[0].cells.len(), 2);
+        assert_eq!(diff.modified.len(), 1);
+        assert_eq!(
+            diff.modified[0].cells,
+            vec![CellChange {
+                column: 1,
+                before: json!("b"),
+                after: json!("x")
+            }]
+        );
+        assert_eq!(diff.user_ids(), vec![2, 3, 4]);
+        assert_eq!(diff.as_records(&HashMap::new()).len(), 5);
+    }
+}
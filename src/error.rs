@@ -12,7 +12,14 @@ pub enum GulpError {
     ParseInt(ParseIntError),
     Csv(Arc<csv::Error>),
     FromUtf8(FromUtf8Error),
-    Ureq(Arc<ureq::Error>),
+    Xlsx(Arc<rust_xlsxwriter::XlsxError>),
+    Zip(Arc<zip::result::ZipError>),
+    FetchTimeout(String),             // URL
+    FetchTooLarge(String, u64),       // URL, byte limit
+    FetchTooManyRedirects(String),    // URL
+    FetchForbiddenAddress(String),    // host
+    FetchStatus(String, u16),         // URL, HTTP status
+    FetchContentType(String, String), // URL, content type
 }
 
 impl std::error::Error for GulpError {}
@@ -29,7 +36,26 @@ impl std::fmt::Display for GulpError {
             Self::ParseInt(e) => f.write_str(&e.to_string()),
             Self::Csv(e) => f.write_str(&e.to_string()),
             Self::FromUtf8(e) => f.write_str(&e.to_string()),
-            Self::Ureq(e) => f.write_str(&e.to_string()),
+            Self::Xlsx(e) => f.write_str(&e.to_string()),
+            Self::Zip(e) => f.write_str(&e.to_string()),
+            Self::FetchTimeout(url) => write!(f, "Timeout while fetching {url}"),
+            Self::FetchTooLarge(url, max_bytes) => {
+                write!(f, "{url} is larger than the limit of {max_bytes} bytes")
+            }
+            Self::FetchTooManyRedirects(url) => write!(f, "Too many redirects for {url}"),
+            Self::FetchForbiddenAddress(host) => {
+                write!(
+                    f,
+                    "Fetching from {host} is not allowed (private or local address)"
+                )
+            }
+            Self::FetchStatus(url, status) => write!(f, "{url} returned HTTP status {status}"),
+            Self::FetchContentType(url, content_type) => {
+                write!(
+                    f,
+                    "{url} returned unsupported content type '{content_type}'"
+                )
+            }
         }
     }
 }
@@ -94,8 +120,14 @@ impl From<FromUtf8Error> for GulpError {
     }
 }
 
-impl From<ureq::Error> for GulpError {
-    fn from(e: ureq::Error) -> Self {
-        Self::Ureq(Arc::new(e))
+impl From<rust_xlsxwriter::XlsxError> for GulpError {
+    fn from(e: rust_xlsxwriter::XlsxError) -> Self {
+        Self::Xlsx(Arc::new(e))
+    }
+}
+
+impl From<zip::result::ZipError> for GulpError {
+    fn from(e: zip::result::ZipError) -> Self {
+        Self::Zip(Arc::new(e))
     }
 }
@@ -0,0 +1,190 @@
+use crate::cell::Cell;
+use crate::column::ColumnType;
+use crate::header::{DbId, Header, HeaderColumn};
+use crate::list::List;
+use crate::row::Row;
+use crate::GulpError;
+use std::io::{Cursor, Write};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+const MIMETYPE: &str = "application/vnd.oasis.opendocument.spreadsheet";
+const MANIFEST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest:manifest xmlns:manifest="urn:oasis:names:tc:opendocument:xmlns:manifest:1.0" manifest:version="1.2">
+<manifest:file-entry manifest:full-path="/" manifest:version="1.2" manifest:media-type="application/vnd.oasis.opendocument.spreadsheet"/>
+<manifest:file-entry manifest:full-path="content.xml" manifest:media-type="text/xml"/>
+</manifest:manifest>
+"#;
+const CONTENT_START: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" xmlns:xlink="http://www.w3.org/1999/xlink" xmlns:style="urn:oasis:names:tc:opendocument:xmlns:style:1.0" xmlns:number="urn:oasis:names:tc:opendocument:xmlns:datastyle:1.0" office:version="1.2"><office:automatic-styles><number:date-style style:name="N1"><number:year number:style="long"/><number:text>-</number:text><number:month number:style="long"/><number:text>-</number:text><number:day number:style="long"/></number:date-style><style:style style:name="date" style:family="table-cell" style:data-style-name="N1"/></office:automatic-styles><office:body><office:spreadsheet>"#;
+const CONTENT_END: &str = "</office:spreadsheet></office:body></office:document-content>";
+
+/// Writes rows as an ODS spreadsheet, laid out like the XLSX export, including
+/// the units in the header and date cells
+pub fn rows_as_ods(list: &List, rows: &[Row], revision_id: DbId) -> Result<Vec<u8>, GulpError> {
+    let metadata = list.get_export_metadata(revision_id, rows.len());
+    write_ods(&list.header, rows, &metadata)
+}
+
+pub(crate) fn write_ods(
+    header: &Header,
+    rows: &[Row],
+    metadata: &[(&str, String)],
+) -> Result<Vec<u8>, GulpError> {
+    let columns = &header.schema.columns;
+    let mut content = String::from(CONTENT_START);
+    content += r#"<table:table table:name="Rows"><table:table-row>"#;
+    for column in columns {
+        let name = column.display_name_with_unit();
+        if column.column_type == ColumnType::Location {
+            content += &string_cell(&format!("{name} (lat)"), None);
+            content += &string_cell(&format!("{name} (lon)"), None);
+        } else {
+            content += &string_cell(&name, None);
+        }
+    }
+    content += "</table:table-row>";
+    for row in rows {
+        content += "<table:table-row>";
+        for (num, column) in columns.iter().enumerate() {
+            content += &match row.cells.get(num) {
+                Some(Some(cell)) => cell_xml(cell, column),
+                _ if column.column_type == ColumnType::Location => {
+                    "<table:table-cell/><table:table-cell/>".to_string()
+                }
+                _ => "<table:table-cell/>".to_string(),
+            };
+        }
+        content += "</table:table-row>";
+    }
+    content += r#"</table:table><table:table table:name="Metadata">"#;
+    for (key, value) in metadata {
+        content += "<table:table-row>";
+        content += &string_cell(key, None);
+        content += &string_cell(value, None);
+        content += "</table:table-row>";
+    }
+    content += "</table:table>";
+    content += CONTENT_END;
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    // The mimetype has to be the first file, uncompressed
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file("mimetype", stored)?;
+    zip.write_all(MIMETYPE.as_bytes())?;
+    zip.start_file("META-INF/manifest.xml", deflated)?;
+    zip.write_all(MANIFEST.as_bytes())?;
+    zip.start_file("content.xml", deflated)?;
+    zip.write_all(content.as_bytes())?;
+    Ok(zip.finish()?.into_inner())
+}
+
+fn cell_xml(cell: &Cell, column: &HeaderColumn) -> String {
+    match cell {
+        Cell::Number(number) if number.unit == column.unit => float_cell(number.value),
+        Cell::Date(date) => match date.as_naive_datetime() {
+            Some(datetime) => format!(
+                r#"<table:table-cell table:style-name="date" office:value-type="date" office:date-value="{}"><text:p>{}</text:p></table:table-cell>"#,
+                datetime.format("%Y-%m-%dT%H:%M:%S"),
+                xml_escape(&cell.as_string(column))
+            ),
+            None => string_cell(&cell.as_string(column), None),
+        },
+        Cell::Boolean(b) => format!(
+            r#"<table:table-cell office:value-type="boolean" office:boolean-value="{b}"><text:p>{}</text:p></table:table-cell>"#,
+            if *b { "TRUE" } else { "FALSE" }
+        ),
+        Cell::Location(location) => float_cell(location.lat) + &float_cell(location.lon),
+        _ => string_cell(&cell.as_string(column), cell.url(column)),
+    }
+}
+
+fn float_cell(value: f64) -> String {
+    format!(
+        r#"<table:table-cell office:value-type="float" office:value="{value}"><text:p>{value}</text:p></table:table-cell>"#
+    )
+}
+
+fn string_cell(text: &str, url: Option<String>) -> String {
+    let text = xml_escape(text);
+    let text = match url {
+        Some(url) => format!(
+            r#"<text:a xlink:type="simple" xlink:href="{}">{text}</text:a>"#,
+            xml_escape(&url)
+        ),
+        None => text,
+    };
+    format!(
+        r#"<table:table-cell office:value-type="string"><text:p>{text}</text:p></table:table-cell>"#
+    )
+}
+
+/// Escapes markup, and drops control characters that are not allowed in XML
+fn xml_escape(s: &str) -> String {
+    s.chars()
+        .filter(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r'))
+        .map(|c| match c {
+            '&' => "&amp;".to_string(),
+            '<' => "&lt;".to_string(),
+            '>' => "&gt;".to_string(),
+            '"' => "&quot;".to_string(),
+            '\'' => "&apos;".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::HeaderSchema;
+    use calamine::{Data, Reader};
+
+    #[test]
+    fn test_write_ods() {
+        let mut header = Header::new();
+        header.schema = HeaderSchema::from_name_json(
+            "Test",
+            r#"{"columns":[{"column_type":"String","label":"Name"},{"column_type":"Number","unit":"m"},{"column_type":"Boolean"},{"column_type":"Date"}]}"#,
+        )
+        .unwrap();
+        let columns = &header.schema.columns;
+        let rows = vec![
+            Row::from_cells(vec![
+                Some(Cell::String("<Foo & bar>".to_string())),
+                Cell::from_value(&serde_json::json!(1.5), &columns[1]),
+                Some(Cell::Boolean(true)),
+                Cell::from_value(&serde_json::json!("2001-12-31"), &columns[3]),
+            ]),
+            Row::from_cells(vec![
+                None,
+                Cell::from_value(&serde_json::json!("3 km"), &columns[1]),
+                None,
+                None,
+            ]),
+        ];
+        let metadata = [("List", "Test".to_string())];
+        let data = write_ods(&header, &rows, &metadata).unwrap();
+
+        let mut ods: calamine::Ods<_> = calamine::open_workbook_from_rs(Cursor::new(data)).unwrap();
+        assert_eq!(ods.sheet_names(), vec!["Rows", "Metadata"]);
+        let range = ods.worksheet_range("Rows").unwrap();
+        assert_eq!(range.get_value((0, 0)), Some(&Data::String("Name".into())));
+        assert_eq!(
+            range.get_value((1, 0)),
+            Some(&Data::String("<Foo & bar>".into()))
+        );
+        assert_eq!(range.get_value((1, 1)), Some(&Data::Float(1.5)));
+        assert_eq!(range.get_value((1, 2)), Some(&Data::Bool(true)));
+        assert_eq!(
+            range.get_value((1, 3)),
+            Some(&Data::DateTimeIso("2001-12-31T00:00:00".into()))
+        );
+        assert_eq!(range.get_value((2, 1)), Some(&Data::String("3 km".into())));
+        assert_eq!(
+            range.get_value((0, 1)),
+            Some(&Data::String("number (m)".into()))
+        );
+    }
+}
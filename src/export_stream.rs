@@ -0,0 +1,186 @@
+use crate::gulp_response::ContentType;
+use crate::header::{DbId, Header};
+use crate::list::List;
+use crate::row::Row;
+use crate::row_filter::RowFilter;
+use crate::GulpError;
+use csv::WriterBuilder;
+use futures::Stream;
+
+/// Rows read from the database at a time when streaming
+const CHUNK_SIZE: DbId = 5000;
+
+struct StreamState {
+    list: List,
+    revision_id: DbId,
+    format: ContentType,
+    filter: RowFilter,
+    after_row_num: DbId,
+    skip: usize,
+    remaining: usize,
+    with_header: bool,
+    done: bool,
+}
+
+/// Writes the rows of a revision as CSV, TSV, or JSONL, reading them chunk by chunk
+/// so memory use does not depend on the list size. `start` and `length` apply to
+/// the rows matching `filter`.
+pub fn stream_rows(
+    list: List,
+    revision_id: DbId,
+    format: ContentType,
+    filter: RowFilter,
+    start: usize,
+    length: Option<usize>,
+    with_header: bool,
+) -> impl Stream<Item = Result<String, GulpError>> + Send {
+    let state = StreamState {
+        list,
+        revision_id,
+        format,
+        filter,
+        after_row_num: 0,
+        skip: start,
+        remaining: length.unwrap_or(usize::MAX),
+        with_header,
+        done: false,
+    };
+    futures::stream::try_unfold(state, |mut state| async move {
+        loop {
+            if state.done || state.remaining == 0 {
+                return Ok(None);
+            }
+            let rows = state
+                .list
+                .get_rows_for_revision_after(state.revision_id, state.after_row_num, CHUNK_SIZE)
+                .await?;
+            state.done = (rows.len() as DbId) < CHUNK_SIZE;
+            if let Some(row) = rows.last() {
+                state.after_row_num = row.row_num;
+            }
+            let rows: Vec<Row> = rows
+                .into_iter()
+                .filter(|row| state.filter.matches(row, &state.list.header))
+                .collect();
+            let skip = state.skip.min(rows.len());
+            state.skip -= skip;
+            let rows: Vec<Row> = rows.into_iter().skip(skip).take(state.remaining).collect();
+            state.remaining -= rows.len();
+            if rows.is_empty() && !state.with_header {
+                continue;
+            }
+            let text = rows_as_text(&state.list, &rows, &state.format, state.with_header)?;
+            state.with_header = false;
+            return Ok(Some((text, state)));
+        }
+    })
+}
+
+/// Writes rows as CSV, TSV, or JSONL
+pub fn rows_as_text(
+    list: &List,
+    rows: &[Row],
+    format: &ContentType,
+    with_header: bool,
+) -> Result<String, GulpError> {
+    match format {
+        ContentType::CSV => rows_as_xsv(&list.header, rows, b',', with_header),
+        ContentType::TSV => rows_as_xsv(&list.header, rows, b'\t', with_header),
+        ContentType::JSONL => rows_as_jsonl(list, rows, with_header),
+        other => Err(format!("Output format '{}' is not a text format", other.as_str()).into()),
+    }
+}
+
+/// Writes rows as CSV or TSV; every record has the row number and one field per column
+fn rows_as_xsv(
+    header: &Header,
+    rows: &[Row],
+    delimiter: u8,
+    with_header: bool,
+) -> Result<String, GulpError> {
+    let mut wtr = WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(vec![]);
+    if with_header {
+        // Rows start with the row number
+        let mut names = header.schema.column_names();
+        names.insert(0, "row".to_string());
+        wtr.write_record(names)?;
+    }
+    for row in rows {
+        wtr.write_record(&row.as_vec(header))?;
+    }
+    let inner = wtr
+        .into_inner()
+        .map_err(|e| GulpError::String(e.to_string()))?;
+    let ret = String::from_utf8(inner)?;
+    Ok(ret)
+}
+
+/// One line per row with the cells as in JSON output, preceded by a line with the
+/// header schema; a JSONL data source reproduces the rows from this
+fn rows_as_jsonl(list: &List, rows: &[Row], with_header: bool) -> Result<String, GulpError> {
+    let mut ret = String::new();
+    if with_header {
+        ret += &serde_json::to_string(&list.header.schema)?;
+        ret.push('\n');
+    }
+    for row in rows {
+        ret += &row.as_json(&list.header)["c"].to_string();
+        ret.push('\n');
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state::*;
+    use crate::cell::Cell;
+    use crate::header::HeaderSchema;
+    use futures::TryStreamExt;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_rows_as_xsv() {
+        let mut header = Header::new();
+        header.schema = HeaderSchema::from_name_json(
+            "Test",
+            r#"{"columns":[{"column_type":"String","label":"Name"},{"column_type":"String"}]}"#,
+        )
+        .unwrap();
+        let mut row = Row::from_cells(vec![Some(Cell::String("a,b".to_string())), None]);
+        row.row_num = 7;
+        // Cells are missing at the end, e.g. for a column added later
+        let mut short_row = Row::from_cells(vec![Some(Cell::String("c".to_string()))]);
+        short_row.row_num = 8;
+        let csv = rows_as_xsv(&header, &[row, short_row], b',', true).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines, vec!["row,Name,text", r#"7,"a,b","#, "8,c,"]);
+
+        let tsv = rows_as_xsv(&header, &[], b'\t', true).unwrap();
+        assert_eq!(tsv, "row\tName\ttext\n");
+    }
+
+    #[tokio::test]
+    async fn test_stream_rows() {
+        let app = AppState::from_config_file("config.json").expect("app creation failed");
+        let app = Arc::new(app);
+        let list = List::from_id(&app, 4).await.expect("from_id fail");
+        let rows = list.get_rows_for_revision(list.revision_id).await.unwrap();
+        let expected = rows_as_text(&list, &rows, &ContentType::TSV, true).unwrap();
+        let chunks: Vec<String> = stream_rows(
+            list.clone(),
+            list.revision_id,
+            ContentType::TSV,
+            RowFilter::default(),
+            0,
+            None,
+            true,
+        )
+        .try_collect()
+        .await
+        .unwrap();
+        assert_eq!(chunks.concat(), expected);
+    }
+}
@@ -0,0 +1,259 @@
+use crate::cell::{Cell, Date};
+use crate::column::ColumnType;
+use crate::header::{DbId, Header, HeaderColumn};
+use crate::list::List;
+use crate::row::Row;
+use crate::GulpError;
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use rust_xlsxwriter::{ExcelDateTime, Format, Url, Workbook, Worksheet, XlsxError};
+
+const MAX_ROWS: usize = 1_048_576; // Excel limit, including the header row
+
+/// Writes rows as an XLSX workbook, with a "Rows" sheet (header row first) and
+/// a "Metadata" sheet. Locations take two numeric columns (lat, lon), dates
+/// are date cells. Number columns have their unit in the header; numbers with
+/// a different unit are written as text, e.g. "3 km".
+pub fn rows_as_xlsx(list: &List, rows: &[Row], revision_id: DbId) -> Result<Vec<u8>, GulpError> {
+    let metadata = list.get_export_metadata(revision_id, rows.len());
+    write_xlsx(&list.header, rows, &metadata)
+}
+
+fn write_xlsx(
+    header: &Header,
+    rows: &[Row],
+    metadata: &[(&str, String)],
+) -> Result<Vec<u8>, GulpError> {
+    if rows.len() >= MAX_ROWS {
+        return Err(format!("Too many rows for XLSX: {}", rows.len()).into());
+    }
+    let columns = &header.schema.columns;
+    let bold = Format::new().set_bold();
+
+    let mut sheet = Worksheet::new();
+    sheet.set_name("Rows")?;
+    let mut xlsx_col: u16 = 0;
+    for column in columns {
+        let name = column.display_name_with_unit();
+        if column.column_type == ColumnType::Location {
+            sheet.write_string_with_format(0, xlsx_col, format!("{name} (lat)"), &bold)?;
+            sheet.write_string_with_format(0, xlsx_col + 1, format!("{name} (lon)"), &bold)?;
+            xlsx_col += 2;
+        } else {
+            sheet.write_string_with_format(0, xlsx_col, name, &bold)?;
+            xlsx_col += 1;
+        }
+    }
+    for (row_num, row) in rows.iter().enumerate() {
+        let xlsx_row = row_num as u32 + 1;
+        let mut xlsx_col: u16 = 0;
+        for (cell, column) in row.cells.iter().zip(columns.iter()) {
+            if let Some(cell) = cell {
+                write_cell(&mut sheet, xlsx_row, xlsx_col, cell, column)?;
+            }
+            xlsx_col += match column.column_type {
+                ColumnType::Location => 2,
+                _ => 1,
+            };
+        }
+    }
+
+    let mut metadata_sheet = Worksheet::new();
+    metadata_sheet.set_name("Metadata")?;
+    for (num, (key, value)) in metadata.iter().enumerate() {
+        metadata_sheet.write_string_with_format(num as u32, 0, *key, &bold)?;
+        metadata_sheet.write_string(num as u32, 1, value)?;
+    }
+
+    let mut workbook = Workbook::new();
+    workbook.push_worksheet(sheet);
+    workbook.push_worksheet(metadata_sheet);
+    Ok(workbook.save_to_buffer()?)
+}
+
+fn write_cell(
+    sheet: &mut Worksheet,
+    row: u32,
+    col: u16,
+    cell: &Cell,
+    column: &HeaderColumn,
+) -> Result<(), GulpError> {
+    match cell {
+        Cell::Number(number) if number.unit == column.unit => {
+            sheet.write_number(row, col, number.value)?;
+        }
+        Cell::Date(date) => match date.as_naive_datetime().map(|dt| excel_datetime(&dt)) {
+            Some(Ok(datetime)) => {
+                let format = Format::new().set_num_format(date_num_format(date.precision));
+                sheet.write_datetime_with_format(row, col, &datetime, &format)?;
+            }
+            // Outside of the Excel date range
+            _ => {
+                sheet.write_string(row, col, cell.as_string(column))?;
+            }
+        },
+        Cell::Boolean(b) => {
+            sheet.write_boolean(row, col, *b)?;
+        }
+        Cell::Location(location) => {
+            sheet.write_number(row, col, location.lat)?;
+            sheet.write_number(row, col + 1, location.lon)?;
+        }
+        _ => {
+            let text = cell.as_string(column);
+            let linked = match cell.url(column) {
+                // Excel rejects overly long URLs; those become plain text
+                Some(url) => sheet
+                    .write_url(row, col, Url::new(url).set_text(&text))
+                    .is_ok(),
+                None => false,
+            };
+            if !linked {
+                sheet.write_string(row, col, text)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn excel_datetime(datetime: &NaiveDateTime) -> Result<ExcelDateTime, XlsxError> {
+    ExcelDateTime::from_ymd(
+        datetime.year() as u16,
+        datetime.month() as u8,
+        datetime.day() as u8,
+    )?
+    .and_hms(
+        datetime.hour() as u16,
+        datetime.minute() as u8,
+        datetime.second(),
+    )
+}
+
+/// Only shows the parts of a date that its precision covers
+fn date_num_format(precision: u8) -> &'static str {
+    match precision {
+        0..=Date::PRECISION_YEAR => "yyyy",
+        Date::PRECISION_MONTH => "yyyy-mm",
+        Date::PRECISION_DAY => "yyyy-mm-dd",
+        Date::PRECISION_HOUR | Date::PRECISION_MINUTE => "yyyy-mm-dd hh:mm",
+        _ => "yyyy-mm-dd hh:mm:ss",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::HeaderSchema;
+    use calamine::{Data, Reader, Xlsx};
+    use serde_json::json;
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn test_write_xlsx() {
+        let mut header = Header::new();
+        header.schema = HeaderSchema::from_name_json(
+            "Test",
+            r#"{"columns":[
+                {"column_type":"String","label":"Name"},
+                {"column_type":"Location","label":"Place"},
+                {"column_type":"Number","label":"Height","unit":"m"},
+                {"column_type":"Boolean","label":"Done"},
+                {"column_type":"WikiPage","label":"Page","wiki":"enwiki","namespace_id":0},
+                {"column_type":"Date","label":"When"}
+            ]}"#,
+        )
+        .unwrap();
+        let columns = &header.schema.columns;
+        let rows = vec![
+            Row::from_cells(vec![
+                Some(Cell::String("Foo".to_string())),
+                Cell::from_value(&json!({"lat":52.5,"lon":-13.25}), &columns[1]),
+                Cell::from_value(&json!(1.5), &columns[2]),
+                Some(Cell::Boolean(true)),
+                Cell::from_value(&json!("Berlin"), &columns[4]),
+                Cell::from_value(&json!("2001-12-31"), &columns[5]),
+            ]),
+            Row::from_cells(vec![
+                None,
+                None,
+                Cell::from_value(&json!("3 km"), &columns[2]),
+                Some(Cell::Boolean(false)),
+                None,
+                Cell::from_value(&json!("-500"), &columns[5]),
+            ]),
+        ];
+        let metadata = [("List", "Test".to_string()), ("Rows", "2".to_string())];
+        let data = write_xlsx(&header, &rows, &metadata).unwrap();
+
+        // The page cell links to the page
+        let mut zip = zip::ZipArchive::new(Cursor::new(data.clone())).unwrap();
+        let mut rels = String::new();
+        zip.by_name("xl/worksheets/_rels/sheet1.xml.rels")
+            .unwrap()
+            .read_to_string(&mut rels)
+            .unwrap();
+        assert!(rels.contains(r#"Target="https://en.wikipedia.org/wiki/Berlin""#));
+
+        let mut xlsx: Xlsx<_> = calamine::open_workbook_from_rs(Cursor::new(data)).unwrap();
+        assert_eq!(xlsx.sheet_names(), vec!["Rows", "Metadata"]);
+        let range = xlsx.worksheet_range("Rows").unwrap();
+        let row = |num: usize| -> Vec<Data> {
+            (0..7)
+                .map(|col| {
+                    range
+                        .get_value((num as u32, col))
+                        .cloned()
+                        .unwrap_or(Data::Empty)
+                })
+                .collect()
+        };
+        let text = |s: &str| Data::String(s.to_string());
+        assert_eq!(
+            row(0),
+            vec![
+                text("Name"),
+                text("Place (lat)"),
+                text("Place (lon)"),
+                text("Height (m)"),
+                text("Done"),
+                text("Page"),
+                text("When")
+            ]
+        );
+        assert_eq!(
+            row(1)[..6],
+            [
+                text("Foo"),
+                Data::Float(52.5),
+                Data::Float(-13.25),
+                Data::Float(1.5),
+                Data::Bool(true),
+                text("Berlin")
+            ]
+        );
+        // A real date cell
+        let datetime = match &row(1)[6] {
+            Data::DateTime(dt) => dt.as_datetime(),
+            other => panic!("Not a date: {:?}", other),
+        };
+        let expected =
+            chrono::NaiveDate::from_ymd_opt(2001, 12, 31).and_then(|d| d.and_hms_opt(0, 0, 0));
+        assert_eq!(datetime, expected);
+        assert_eq!(
+            row(2),
+            vec![
+                Data::Empty,
+                Data::Empty,
+                Data::Empty,
+                text("3 km"),
+                Data::Bool(false),
+                Data::Empty,
+                text("-0500")
+            ]
+        );
+
+        let range = xlsx.worksheet_range("Metadata").unwrap();
+        assert_eq!(range.get_value((0, 0)), Some(&text("List")));
+        assert_eq!(range.get_value((0, 1)), Some(&text("Test")));
+        assert_eq!(range.get_value((1, 1)), Some(&text("2")));
+    }
+}
@@ -0,0 +1,417 @@
+use crate::GulpError;
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::header::{
+    HeaderMap, ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, ETAG, LAST_MODIFIED, LOCATION,
+};
+use reqwest::{redirect, StatusCode, Url};
+use serde_json::Value;
+use std::io::{self, Seek, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::NamedTempFile;
+
+const DEFAULT_TIMEOUT_SEC: u64 = 300;
+const DEFAULT_CONNECT_TIMEOUT_SEC: u64 = 10;
+const DEFAULT_MAX_BYTES: u64 = 1024 * 1024 * 1024;
+const DEFAULT_MAX_REDIRECTS: usize = 5;
+
+/// Responses of these types are error pages or web sites, not data
+const REFUSED_CONTENT_TYPES: &[&str] = &["text/html", "application/xhtml+xml"];
+
+/// Limits for downloads from user-supplied URLs; the "fetcher" object in the config file
+#[derive(Clone, Debug)]
+pub struct FetcherConfig {
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+    pub max_bytes: u64,
+    pub max_redirects: usize,
+    /// Allow loopback, private, and link-local addresses (for local testing only)
+    pub allow_private_addresses: bool,
+}
+
+impl FetcherConfig {
+    pub fn from_config(config: &Value) -> Self {
+        Self {
+            timeout: Duration::from_secs(
+                config["timeout_sec"]
+                    .as_u64()
+                    .unwrap_or(DEFAULT_TIMEOUT_SEC),
+            ),
+            connect_timeout: Duration::from_secs(
+                config["connect_timeout_sec"]
+                    .as_u64()
+                    .unwrap_or(DEFAULT_CONNECT_TIMEOUT_SEC),
+            ),
+            max_bytes: config["max_bytes"].as_u64().unwrap_or(DEFAULT_MAX_BYTES),
+            max_redirects: config["max_redirects"]
+                .as_u64()
+                .map(|n| n as usize)
+                .unwrap_or(DEFAULT_MAX_REDIRECTS),
+            allow_private_addresses: config["allow_private_addresses"].as_bool().unwrap_or(false),
+        }
+    }
+}
+
+/// ETag and Last-Modified of an earlier download, for conditional requests
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HttpValidators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl HttpValidators {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let get = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|s| s.to_string())
+        };
+        Self {
+            etag: get(ETAG),
+            last_modified: get(LAST_MODIFIED),
+        }
+    }
+}
+
+pub enum UrlFetch {
+    NotModified,
+    Downloaded {
+        file: NamedTempFile,
+        content_md5: String,
+        validators: HttpValidators,
+    },
+}
+
+/// Fetches user-supplied URLs, within the limits of a `FetcherConfig`
+#[derive(Clone, Debug)]
+pub struct Fetcher {
+    client: reqwest::Client,
+    config: FetcherConfig,
+}
+
+impl Fetcher {
+    pub fn new(config: FetcherConfig) -> Result<Self, GulpError> {
+        let client = reqwest::Client::builder()
+            .user_agent("gulp/0.1")
+            .timeout(config.timeout)
+            .connect_timeout(config.connect_timeout)
+            // Redirects are followed in `send`, so each target can be checked
+            .redirect(redirect::Policy::none())
+            .dns_resolver(Arc::new(PublicResolver {
+                allow_private_addresses: config.allow_private_addresses,
+            }))
+            .build()?;
+        Ok(Self { client, config })
+    }
+
+    /// The size limit for downloads, and for their uncompressed content
+    pub fn max_bytes(&self) -> u64 {
+        self.config.max_bytes
+    }
+
+    /// Downloads `url` into a temporary file in `dir`. The server can answer that nothing
+    /// changed since the download that `validators` came from. `accept` is sent as the
+    /// Accept header, for servers that offer several formats.
+    pub async fn fetch_to_file(
+        &self,
+        url: &str,
+        accept: Option<&str>,
+        validators: &HttpValidators,
+        dir: &Path,
+    ) -> Result<UrlFetch, GulpError> {
+        let mut headers = HeaderMap::new();
+        if let Some(accept) = accept.and_then(|s| s.parse().ok()) {
+            headers.insert(ACCEPT, accept);
+        }
+        if let Some(etag) = validators.etag.as_ref().and_then(|s| s.parse().ok()) {
+            headers.insert(reqwest::header::IF_NONE_MATCH, etag);
+        }
+        if let Some(date) = validators
+            .last_modified
+            .as_ref()
+            .and_then(|s| s.parse().ok())
+        {
+            headers.insert(reqwest::header::IF_MODIFIED_SINCE, date);
+        }
+        let mut response = self.send(url, headers).await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(UrlFetch::NotModified);
+        }
+        let validators = HttpValidators::from_headers(response.headers());
+        let mut file = NamedTempFile::new_in(dir)?;
+        let mut writer = Md5Writer::new(file.as_file_mut());
+        self.read_body(url, &mut response, &mut writer).await?;
+        let content_md5 = writer.hex_digest();
+        file.rewind()?;
+        Ok(UrlFetch::Downloaded {
+            file,
+            content_md5,
+            validators,
+        })
+    }
+
+    /// Writes the response body to `out`, up to the byte limit
+    async fn read_body(
+        &self,
+        url: &str,
+        response: &mut reqwest::Response,
+        out: &mut impl Write,
+    ) -> Result<(), GulpError> {
+        let mut bytes: u64 = 0;
+        while let Some(chunk) = response.chunk().await.map_err(|e| self.map_error(url, e))? {
+            bytes += chunk.len() as u64;
+            if bytes > self.config.max_bytes {
+                return Err(GulpError::FetchTooLarge(
+                    url.to_string(),
+                    self.config.max_bytes,
+                ));
+            }
+            out.write_all(&chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Sends a GET request, following redirects, and checks the response
+    async fn send(&self, url: &str, headers: HeaderMap) -> Result<reqwest::Response, GulpError> {
+        let mut current = Url::parse(url).map_err(|e| format!("Invalid URL '{url}': {e}"))?;
+        let mut redirects = 0;
+        loop {
+            self.check_url(&current).await?;
+            let response = self
+                .client
+                .get(current.clone())
+                .headers(headers.clone())
+                .send()
+                .await
+                .map_err(|e| self.map_error(url, e))?;
+            if response.status().is_redirection() && response.status() != StatusCode::NOT_MODIFIED {
+                redirects += 1;
+                if redirects > self.config.max_redirects {
+                    return Err(GulpError::FetchTooManyRedirects(url.to_string()));
+                }
+                let location = response
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|location| location.to_str().ok())
+                    .ok_or_else(|| format!("Redirect without location from {current}"))?;
+                current = current
+                    .join(location)
+                    .map_err(|e| format!("Invalid redirect to '{location}': {e}"))?;
+                continue;
+            }
+            return self.check_response(url, response);
+        }
+    }
+
+    fn check_response(
+        &self,
+        url: &str,
+        response: reqwest::Response,
+    ) -> Result<reqwest::Response, GulpError> {
+        let status = response.status();
+        if !status.is_success() && status != StatusCode::NOT_MODIFIED {
+            return Err(GulpError::FetchStatus(url.to_string(), status.as_u16()));
+        }
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+        if let Some(content_type) = header(CONTENT_TYPE) {
+            let mime = content_type.split(';').next().unwrap_or_default().trim();
+            if REFUSED_CONTENT_TYPES
+                .iter()
+                .any(|refused| mime.eq_ignore_ascii_case(refused))
+            {
+                return Err(GulpError::FetchContentType(
+                    url.to_string(),
+                    content_type.to_string(),
+                ));
+            }
+        }
+        let content_length = header(CONTENT_LENGTH).and_then(|s| s.parse::<u64>().ok());
+        if content_length.is_some_and(|length| length > self.config.max_bytes) {
+            return Err(GulpError::FetchTooLarge(
+                url.to_string(),
+                self.config.max_bytes,
+            ));
+        }
+        Ok(response)
+    }
+
+    /// Only HTTP(S) to public addresses. Host names are resolved by `PublicResolver`
+    /// as well, which also covers DNS answers changing between check and request.
+    async fn check_url(&self, url: &Url) -> Result<(), GulpError> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(format!("Unsupported URL scheme '{}'", url.scheme()).into());
+        }
+        if self.config.allow_private_addresses {
+            return Ok(());
+        }
+        let host = url
+            .host_str()
+            .ok_or_else(|| format!("No host in URL {url}"))?;
+        let port = url.port_or_known_default().unwrap_or(80);
+        // IPv6 hosts come in brackets
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port)).await?.collect();
+        if addrs.iter().any(|addr| !is_public_ip(&addr.ip())) {
+            return Err(GulpError::FetchForbiddenAddress(host.to_string()));
+        }
+        Ok(())
+    }
+
+    fn map_error(&self, url: &str, e: reqwest::Error) -> GulpError {
+        if e.is_timeout() {
+            GulpError::FetchTimeout(url.to_string())
+        } else {
+            e.into()
+        }
+    }
+}
+
+/// Resolves host names, dropping non-public addresses unless they are allowed
+struct PublicResolver {
+    allow_private_addresses: bool,
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let allow_private_addresses = self.allow_private_addresses;
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| allow_private_addresses || is_public_ip(&addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                let e = GulpError::FetchForbiddenAddress(name.as_str().to_string());
+                return Err(Box::new(e) as Box<dyn std::error::Error + Send + Sync>);
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+fn is_public_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || octets[0] == 0
+                || (octets[0] == 100 && (octets[1] & 0xc0) == 64)) // carrier-grade NAT
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_ip(&IpAddr::V4(ip));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || (first & 0xfe00) == 0xfc00 // unique local
+                || (first & 0xffc0) == 0xfe80) // link-local
+        }
+    }
+}
+
+/// Passes data through to `inner`, computing its MD5 on the way
+struct Md5Writer<W: Write> {
+    inner: W,
+    context: md5::Context,
+}
+
+impl<W: Write> Md5Writer<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            context: md5::Context::new(),
+        }
+    }
+
+    fn hex_digest(self) -> String {
+        format!("{:x}", self.context.compute())
+    }
+}
+
+impl<W: Write> Write for Md5Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.context.consume(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_md5_writer() {
+        let data = "a,b\n1,2\n".repeat(10000);
+        let mut out = vec![];
+        let mut writer = Md5Writer::new(&mut out);
+        io::copy(&mut data.as_bytes(), &mut writer).unwrap();
+        assert_eq!(writer.hex_digest(), format!("{:x}", md5::compute(&data)));
+        assert_eq!(out, data.as_bytes());
+    }
+
+    #[test]
+    fn test_is_public_ip() {
+        let public = ["8.8.8.8", "208.80.154.224", "2620:0:861:ed1a::1"];
+        for ip in public {
+            assert!(is_public_ip(&ip.parse().unwrap()), "{}", ip);
+        }
+        let private = [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ];
+        for ip in private {
+            assert!(!is_public_ip(&ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_url() {
+        async fn check(config: Value, url: &str) -> Result<(), GulpError> {
+            let fetcher = Fetcher::new(FetcherConfig::from_config(&config)).unwrap();
+            fetcher.check_url(&Url::parse(url).unwrap()).await
+        }
+        assert!(matches!(
+            check(json!({}), "http://127.0.0.1:8000/x.csv").await,
+            Err(GulpError::FetchForbiddenAddress(_))
+        ));
+        assert!(matches!(
+            check(json!({}), "http://[::1]/x.csv").await,
+            Err(GulpError::FetchForbiddenAddress(_))
+        ));
+        assert!(check(json!({}), "ftp://8.8.8.8/x.csv").await.is_err());
+        assert!(check(json!({}), "https://8.8.8.8/x.csv").await.is_ok());
+        let allow_private = json!({"allow_private_addresses":true});
+        assert!(check(allow_private, "http://127.0.0.1:8000/x.csv")
+            .await
+            .is_ok());
+    }
+}
@@ -8,6 +8,9 @@ pub enum ContentType {
     // JSONP,
     CSV,
     TSV,
+    JSONL,
+    XLSX,
+    ODS,
 }
 
 impl ContentType {
@@ -19,6 +22,9 @@ impl ContentType {
             // Self::JSONP => "application/javascript",
             Self::CSV => "text/csv; charset=utf-8",
             Self::TSV => "text/tab-separated-values; charset=utf-8",
+            Self::JSONL => "application/x-ndjson; charset=utf-8",
+            Self::XLSX => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            Self::ODS => "application/vnd.oasis.opendocument.spreadsheet",
         }
     }
 
@@ -27,6 +33,9 @@ impl ContentType {
             "csv" => Some(Self::CSV),
             "tsv" => Some(Self::TSV),
             "json" => Some(Self::JSON),
+            "jsonl" | "ndjson" => Some(Self::JSONL),
+            "xlsx" => Some(Self::XLSX),
+            "ods" => Some(Self::ODS),
             _ => None,
         }
     }
@@ -59,6 +68,9 @@ impl ContentType {
             ContentType::JSON => "json",
             ContentType::CSV => "csv",
             ContentType::TSV => "tsv",
+            ContentType::JSONL => "jsonl",
+            ContentType::XLSX => "xlsx",
+            ContentType::ODS => "ods",
         }
         .to_lowercase()
     }
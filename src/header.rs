@@ -5,16 +5,34 @@ use serde::Serialize;
 use serde_json::json;
 use std::{collections::HashMap, str::FromStr, sync::Arc};
 
-use crate::{app_state::AppState, cell::Cell, column::ColumnType};
+use crate::{
+    app_state::AppState,
+    cell::{Cell, Date},
+    column::ColumnType,
+};
 
 lazy_static! {
-    static ref RE_WIKIDATA: Regex = Regex::new(r#"^[PQ]\d+$"#).expect("Regexp error");
+    pub static ref RE_WIKIDATA: Regex = Regex::new(r#"^[PQLM]\d+$"#).expect("Regexp error");
+    static ref RE_WIKIDATA_PAGE: Regex = Regex::new(r#"^[PQ]\d+$"#).expect("Regexp error");
     static ref RE_WIKIDATA_ITEM: Regex = Regex::new(r#"^Q\d+$"#).expect("Regexp error");
     static ref RE_FILE: Regex =
         Regex::new(r#"^(?i).+\.(jpg|jpeg|tif|tiff|png)$"#).expect("Regexp error");
     pub static ref RE_LOCATION: Regex =
-        Regex::new(r#"^([-+]?\d+|[-+]?\d*\.\d+)°?\s*[,/]?\s*([-+]?\d+|[-+]?\d*\.\d+)°?$"#)
+        Regex::new(r#"^([-+]?\d+|[-+]?\d*\.\d+)°?(?:\s*[,/]\s*|\s+)([-+]?\d+|[-+]?\d*\.\d+)°?$"#)
             .expect("Regexp error");
+    pub static ref RE_NUMBER: Regex =
+        Regex::new(r#"^([-+]?(?:\d+\.?\d*|\.\d+)(?:[eE][-+]?\d+)?)(?:\s*([^\d\s.,:;/+-][^\d]*))?$"#)
+            .expect("Regexp error");
+    pub static ref RE_DATE: Regex = Regex::new(
+        r#"^([-+]?\d{1,16})(?:-(\d{1,2})(?:-(\d{1,2})(?:[T ](\d{1,2})(?::(\d{1,2})(?::(\d{1,2}))?)?)?)?)?Z?$"#
+    )
+    .expect("Regexp error");
+    static ref RE_HINT_WIKIDATA: Regex =
+        Regex::new(r#"^(?i)(q|qid|pid|lid|item|property|lexeme|entity|wikidata|wikidata[ _-]?(id|item|entity))$"#)
+            .expect("Regexp error");
+    static ref RE_HINT_DATE: Regex =
+        Regex::new(r#"(?i)(^|[\s_-])(date|year|time|born|died)($|[\s_-])"#).expect("Regexp error");
+    static ref RE_BOOLEAN: Regex = Regex::new(r#"^(?i)(true|false|yes|no)$"#).expect("Regexp error");
 }
 
 pub type NamespaceType = i64;
@@ -26,6 +44,13 @@ pub struct HeaderColumn {
     pub wiki: Option<String>,
     pub string: Option<String>,
     pub namespace_id: Option<NamespaceType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    /// Human-readable column name, used e.g. for export header lines
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
 }
 
 impl HeaderColumn {
@@ -36,14 +61,21 @@ impl HeaderColumn {
             wiki: Self::value_option_to_string_option(value.get("wiki")),
             namespace_id: Self::value_option_to_namespace_id(value.get("namespace_id")),
             string: Self::value_option_to_string_option(value.get("string")),
+            unit: Self::value_option_to_string_option(value.get("unit")),
+            label: Self::value_option_to_string_option(value.get("label")),
+            description: Self::value_option_to_string_option(value.get("description")),
         })
     }
 
+    /// Guesses the type of a `String` column from its cells. Item and property IDs become
+    /// pages on Wikidata; they are only guessed as `WikidataEntity` if the column name
+    /// asks for it (e.g. "QID" or "item"), as that changes how the cells are shown.
     pub async fn guess(&self, cells: Vec<Cell>) -> HeaderColumn {
         if self.column_type != ColumnType::String
             || self.wiki.is_some()
             || self.string.is_some()
             || self.namespace_id.is_some()
+            || self.unit.is_some()
         {
             return self.to_owned();
         }
@@ -53,11 +85,20 @@ impl HeaderColumn {
             ("total", 0),
             ("not_empty", 0),
             ("wikidata", 0),
+            ("wikidata_page", 0),
             ("wikidata_ns0", 0),
             ("file", 0),
             ("commons_ns6", 0),
             ("location", 0),
+            ("number", 0),
+            ("date", 0),
+            ("date_or_year", 0),
+            ("boolean", 0),
         ]);
+        // The column name (e.g. from a header row) can hint at the type
+        let label = self.label.to_owned().unwrap_or_default();
+        let hint_wikidata = RE_HINT_WIKIDATA.is_match(label.trim());
+        let hint_date = RE_HINT_DATE.is_match(&label);
         for cell in &cells {
             *stats.get_mut("total").unwrap() += 1;
             if !cell.as_string(self).is_empty() {
@@ -67,6 +108,8 @@ impl HeaderColumn {
                 Cell::WikiPage(_) => {} // Ignore
                 Cell::String(s) => {
                     *stats.get_mut("wikidata").unwrap() += RE_WIKIDATA.is_match(s) as usize;
+                    *stats.get_mut("wikidata_page").unwrap() +=
+                        RE_WIKIDATA_PAGE.is_match(s) as usize;
                     *stats.get_mut("wikidata_ns0").unwrap() +=
                         RE_WIKIDATA_ITEM.is_match(s) as usize;
                     if RE_FILE.is_match(s) {
@@ -77,20 +120,77 @@ impl HeaderColumn {
                     if RE_LOCATION.is_match(s) {
                         *stats.get_mut("location").unwrap() += 1;
                     }
+                    if let Some(cap) = RE_NUMBER.captures(s) {
+                        // Only plain numbers, without a unit
+                        *stats.get_mut("number").unwrap() += cap.get(2).is_none() as usize;
+                    }
+                    if let Some(date) = Date::parse(s) {
+                        // A plain year is more likely a number
+                        *stats.get_mut("date").unwrap() +=
+                            (date.precision >= Date::PRECISION_MONTH) as usize;
+                        *stats.get_mut("date_or_year").unwrap() += 1;
+                    }
+                    *stats.get_mut("boolean").unwrap() += RE_BOOLEAN.is_match(s) as usize;
                 }
                 Cell::Location(_) => {
                     *stats.get_mut("location").unwrap() += 1;
                 }
+                Cell::Number(_) => {
+                    *stats.get_mut("number").unwrap() += 1;
+                }
+                Cell::Date(_) => {
+                    *stats.get_mut("date").unwrap() += 1;
+                    *stats.get_mut("date_or_year").unwrap() += 1;
+                }
+                Cell::Boolean(_) => {
+                    *stats.get_mut("boolean").unwrap() += 1;
+                }
+                Cell::WikidataEntity(_) => {
+                    *stats.get_mut("wikidata").unwrap() += 1;
+                }
             }
         }
-        if stats["location"] >= stats["not_empty"] {
-            let ret = HeaderColumn {
-                column_type: ColumnType::Location,
-                wiki: None,
-                string: None,
-                namespace_id: None,
-            };
-            return ret;
+        // Name hints go first; e.g. plain years would otherwise be taken for numbers
+        let hints = [
+            (hint_wikidata, "wikidata", ColumnType::WikidataEntity),
+            (hint_date, "date_or_year", ColumnType::Date),
+        ];
+        for (hint, key, column_type) in hints {
+            if hint && stats["not_empty"] > 0 && stats[key] >= stats["not_empty"] {
+                let ret = HeaderColumn {
+                    column_type,
+                    wiki: None,
+                    string: None,
+                    namespace_id: None,
+                    unit: None,
+                    label: self.label.to_owned(),
+                    description: self.description.to_owned(),
+                };
+                return ret;
+            }
+        }
+        if stats["not_empty"] > 0 {
+            // Typed checks before locations, so that plain numbers are not taken for coordinates
+            let typed = [
+                ("number", ColumnType::Number),
+                ("date", ColumnType::Date),
+                ("boolean", ColumnType::Boolean),
+                ("location", ColumnType::Location),
+            ];
+            for (key, column_type) in typed {
+                if stats[key] >= stats["not_empty"] {
+                    let ret = HeaderColumn {
+                        column_type,
+                        wiki: None,
+                        string: None,
+                        namespace_id: None,
+                        unit: None,
+                        label: self.label.to_owned(),
+                        description: self.description.to_owned(),
+                    };
+                    return ret;
+                }
+            }
         }
         if !pages_to_check.is_empty() {
             let mut best_wiki = "";
@@ -112,6 +212,9 @@ impl HeaderColumn {
                     wiki: Some(best_wiki.into()),
                     string: None,
                     namespace_id: None,
+                    unit: None,
+                    label: self.label.to_owned(),
+                    description: self.description.to_owned(),
                 };
                 return ret;
             }
@@ -121,7 +224,7 @@ impl HeaderColumn {
                 .count_existing_pages("commonswiki", &files_to_check)
                 .await;
         }
-        if stats["wikidata"] == stats["total"] {
+        if stats["wikidata_page"] == stats["total"] {
             let ret = HeaderColumn {
                 column_type: ColumnType::WikiPage,
                 wiki: Some("wikidatawiki".into()),
@@ -131,6 +234,9 @@ impl HeaderColumn {
                 } else {
                     None
                 },
+                unit: None,
+                label: self.label.to_owned(),
+                description: self.description.to_owned(),
             };
             return ret;
         }
@@ -141,12 +247,35 @@ impl HeaderColumn {
                 wiki: Some("commonswiki".into()),
                 string: None,
                 namespace_id: Some(6),
+                unit: None,
+                label: self.label.to_owned(),
+                description: self.description.to_owned(),
             };
             return ret;
         }
         self.to_owned()
     }
 
+    /// The label if there is one, otherwise a generated name
+    pub fn display_name(&self) -> String {
+        match &self.label {
+            Some(label) if !label.trim().is_empty() => label.to_owned(),
+            _ => self.generate_name(),
+        }
+    }
+
+    /// The display name, with the unit of number columns if it is not already part of it.
+    /// Used for spreadsheet exports, where number cells carry no unit.
+    pub fn display_name_with_unit(&self) -> String {
+        let name = self.display_name();
+        match (&self.column_type, &self.unit) {
+            (ColumnType::Number, Some(unit)) if !name.contains(&format!("({unit})")) => {
+                format!("{name} ({unit})")
+            }
+            _ => name,
+        }
+    }
+
     fn uc_first(s: &str) -> String {
         let mut v: Vec<char> = s.chars().collect();
         v[0] = v[0].to_uppercase().nth(0).unwrap_or(v[0]);
@@ -178,6 +307,13 @@ impl HeaderColumn {
                 ret
             }
             ColumnType::Location => "location".into(),
+            ColumnType::Number => match &self.unit {
+                Some(unit) => format!("number ({unit})"),
+                None => "number".into(),
+            },
+            ColumnType::Date => "date".into(),
+            ColumnType::Boolean => "boolean".into(),
+            ColumnType::WikidataEntity => "Wikidata entity".into(),
         }
     }
 
@@ -224,6 +360,8 @@ pub struct HeaderSchema {
     pub id: DbId,
     pub name: String,
     pub columns: Vec<HeaderColumn>,
+    /// Indices of the columns that identify a row; their values must be unique in a list
+    pub key_columns: Vec<usize>,
 }
 
 impl HeaderSchema {
@@ -264,6 +402,7 @@ impl HeaderSchema {
         Some(Self {
             id: 0,
             name: name.to_string(),
+            key_columns: Self::key_columns_from_json(&json, columns.len())?,
             columns,
         })
     }
@@ -278,10 +417,72 @@ impl HeaderSchema {
         Some(Self {
             id: row.get(0)?,
             name: row.get(1)?,
+            key_columns: Self::key_columns_from_json(&json, columns.len())?,
             columns,
         })
     }
 
+    /// Returns None if a key column does not exist
+    fn key_columns_from_json(json: &serde_json::Value, columns: usize) -> Option<Vec<usize>> {
+        let key_columns = match json.get("key_columns") {
+            Some(key_columns) => key_columns.as_array()?,
+            None => return Some(vec![]),
+        };
+        key_columns
+            .iter()
+            .map(|num| num.as_u64().map(|num| num as usize))
+            .map(|num| num.filter(|num| *num < columns))
+            .collect()
+    }
+
+    /// The JSON stored in the database. Schemas without key columns keep their old form.
+    fn as_db_json(&self) -> String {
+        if self.key_columns.is_empty() {
+            json!({"columns":self.columns}).to_string()
+        } else {
+            json!({"columns":self.columns,"key_columns":self.key_columns}).to_string()
+        }
+    }
+
+    /// Returns the key of a row, one string per key column.
+    /// None if a key column is empty.
+    pub fn get_key(&self, cells: &[Option<Cell>]) -> Option<Vec<String>> {
+        self.get_key_for_columns(cells, &self.key_columns)
+    }
+
+    /// MD5 of the key of a row, stored with the row to find rows by key
+    pub fn get_key_md5(&self, cells: &[Option<Cell>]) -> Option<String> {
+        let key = self.get_key(cells)?;
+        Some(format!("{:x}", md5::compute(json!(key).to_string())))
+    }
+
+    pub fn get_key_for_columns(
+        &self,
+        cells: &[Option<Cell>],
+        key_columns: &[usize],
+    ) -> Option<Vec<String>> {
+        key_columns
+            .iter()
+            .map(|num| {
+                let column = self.columns.get(*num)?;
+                let value = cells.get(*num)?.as_ref()?.as_string(column);
+                if value.is_empty() {
+                    None
+                } else {
+                    Some(value)
+                }
+            })
+            .collect()
+    }
+
+    /// One display name per column, e.g. for a header line
+    pub fn column_names(&self) -> Vec<String> {
+        self.columns
+            .iter()
+            .map(|column| column.display_name())
+            .collect()
+    }
+
     pub fn generate_name(&self) -> String {
         let parts: Vec<_> = self
             .columns
@@ -305,7 +506,7 @@ impl HeaderSchema {
         if name.is_empty() {
             name = self.generate_name();
         }
-        let json = json!({"columns":self.columns}).to_string();
+        let json = self.as_db_json();
         let sql = "SELECT id,name,json FROM `header_schema` WHERE `json`=:json";
         if let Some(hs) = conn
             .exec_iter(sql, params! {json})
@@ -326,7 +527,7 @@ impl HeaderSchema {
         }
 
         // Create new row
-        let json = json!({"columns":self.columns}).to_string();
+        let json = self.as_db_json();
         let sql = r#"INSERT INTO `header_schema` (`name`,`json`) VALUES (:name,:json)"#;
         conn.exec_drop(sql, params! {name,json}).await?;
         if let Some(id) = conn.last_insert_id() {
@@ -421,5 +622,120 @@ mod tests {
         assert_eq!(hs.name, "Test");
         assert_eq!(hs.columns.len(), 1);
         assert_eq!(hs.columns[0].column_type, ColumnType::WikiPage);
+        assert!(hs.key_columns.is_empty());
+    }
+
+    #[test]
+    fn test_key_columns() {
+        let json_string = r#"{"columns":[{"column_type":"WikidataEntity"},{"column_type":"String"}],"key_columns":[0]}"#;
+        let hs = HeaderSchema::from_name_json("Test", json_string).expect("from_name_json error");
+        assert_eq!(hs.key_columns, vec![0]);
+        let cells = vec![Some(Cell::WikidataEntity("Q42".into())), None];
+        assert_eq!(hs.get_key(&cells), Some(vec!["Q42".to_string()]));
+        assert_eq!(hs.get_key(&[None, None]), None);
+        let key_md5 = hs.get_key_md5(&cells).unwrap();
+        let other_cells = vec![
+            Some(Cell::WikidataEntity("Q42".into())),
+            Some(Cell::String("x".into())),
+        ];
+        assert_eq!(hs.get_key_md5(&other_cells), Some(key_md5));
+        assert_eq!(hs.get_key_md5(&[None, None]), None);
+        let json_string = r#"{"columns":[{"column_type":"String"}],"key_columns":[1]}"#;
+        assert!(HeaderSchema::from_name_json("Test", json_string).is_none());
+    }
+
+    #[test]
+    fn test_column_names() {
+        let json_string = r#"{"columns":[{"column_type":"WikidataEntity","label":"Item","description":"The item"},{"column_type":"Number","unit":"kg"}]}"#;
+        let hs = HeaderSchema::from_name_json("Test", json_string).expect("from_name_json error");
+        assert_eq!(hs.columns[0].description, Some("The item".to_string()));
+        assert_eq!(hs.column_names(), vec!["Item", "number (kg)"]);
+        assert!(hs.as_db_json().contains(r#""label":"Item""#));
+    }
+
+    #[tokio::test]
+    async fn test_guess_typed_columns() {
+        let column = HeaderColumn::from_value(&json!({"column_type":"String"})).unwrap();
+        let cells = ["1", "2.5", "-3e2"]
+            .iter()
+            .map(|s| Cell::String(s.to_string()))
+            .collect();
+        assert_eq!(column.guess(cells).await.column_type, ColumnType::Number);
+        let cells = ["2001-12-31", "1999-01"]
+            .iter()
+            .map(|s| Cell::String(s.to_string()))
+            .collect();
+        assert_eq!(column.guess(cells).await.column_type, ColumnType::Date);
+        let cells = ["yes", "No", "TRUE"]
+            .iter()
+            .map(|s| Cell::String(s.to_string()))
+            .collect();
+        assert_eq!(column.guess(cells).await.column_type, ColumnType::Boolean);
+        let cells = ["52.5, 13.4", "-33.9 151.2"]
+            .iter()
+            .map(|s| Cell::String(s.to_string()))
+            .collect();
+        assert_eq!(column.guess(cells).await.column_type, ColumnType::Location);
+    }
+
+    #[tokio::test]
+    async fn test_guess_numbers_and_dates_are_not_locations() {
+        let column = HeaderColumn::from_value(&json!({"column_type":"String"})).unwrap();
+        let cells = ["10", "25", "300"]
+            .iter()
+            .map(|s| Cell::String(s.to_string()))
+            .collect();
+        assert_eq!(column.guess(cells).await.column_type, ColumnType::Number);
+        let cells = ["1999-01", "2001-12"]
+            .iter()
+            .map(|s| Cell::String(s.to_string()))
+            .collect();
+        assert_eq!(column.guess(cells).await.column_type, ColumnType::Date);
+        assert!(!RE_LOCATION.is_match("3.5"));
+        assert!(!RE_LOCATION.is_match("300"));
+        assert!(RE_LOCATION.is_match("3.5/12"));
+    }
+
+    #[tokio::test]
+    async fn test_guess_with_label_hints() {
+        let column =
+            HeaderColumn::from_value(&json!({"column_type":"String","label":"Year"})).unwrap();
+        let cells = ["1990", "", "2001"]
+            .iter()
+            .map(|s| Cell::String(s.to_string()))
+            .collect();
+        let guessed = column.guess(cells).await;
+        assert_eq!(guessed.column_type, ColumnType::Date);
+        assert_eq!(guessed.label, Some("Year".to_string()));
+        let column =
+            HeaderColumn::from_value(&json!({"column_type":"String","label":"QID"})).unwrap();
+        let cells = ["Q42", "", "Q1"]
+            .iter()
+            .map(|s| Cell::String(s.to_string()))
+            .collect();
+        assert_eq!(
+            column.guess(cells).await.column_type,
+            ColumnType::WikidataEntity
+        );
+    }
+
+    #[tokio::test]
+    async fn test_guess_wikidata_pages_without_hint() {
+        let column = HeaderColumn::from_value(&json!({"column_type":"String"})).unwrap();
+        let cells = ["Q4115189", "Q13406268"]
+            .iter()
+            .map(|s| Cell::String(s.to_string()))
+            .collect();
+        let guessed = column.guess(cells).await;
+        assert_eq!(guessed.column_type, ColumnType::WikiPage);
+        assert_eq!(guessed.wiki, Some("wikidatawiki".to_string()));
+        assert_eq!(guessed.namespace_id, Some(0));
+        let cells = ["Q4115189", "P1476"]
+            .iter()
+            .map(|s| Cell::String(s.to_string()))
+            .collect();
+        let guessed = column.guess(cells).await;
+        assert_eq!(guessed.column_type, ColumnType::WikiPage);
+        assert_eq!(guessed.namespace_id, None);
     }
 }
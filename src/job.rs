@@ -0,0 +1,361 @@
+use crate::app_state::AppState;
+use crate::data_source::DataSource;
+use crate::header::DbId;
+use crate::list::UpdateStats;
+use crate::row::Row;
+use crate::GulpError;
+use mysql_async::prelude::*;
+use serde::Serialize;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How often the worker looks for queued jobs when it is not notified of new ones
+const JOB_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum JobState {
+    QUEUED,
+    DOWNLOADING,
+    PARSING,
+    INSERTING,
+    DONE,
+    FAILED,
+}
+
+impl JobState {
+    pub fn new(s: &str) -> Option<Self> {
+        match s.trim().to_uppercase().as_str() {
+            "QUEUED" => Some(Self::QUEUED),
+            "DOWNLOADING" => Some(Self::DOWNLOADING),
+            "PARSING" => Some(Self::PARSING),
+            "INSERTING" => Some(Self::INSERTING),
+            "DONE" => Some(Self::DONE),
+            "FAILED" => Some(Self::FAILED),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for JobState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::QUEUED => write!(f, "QUEUED"),
+            Self::DOWNLOADING => write!(f, "DOWNLOADING"),
+            Self::PARSING => write!(f, "PARSING"),
+            Self::INSERTING => write!(f, "INSERTING"),
+            Self::DONE => write!(f, "DONE"),
+            Self::FAILED => write!(f, "FAILED"),
+        }
+    }
+}
+
+/// An update of a list from a data source, run in the background by `run_worker`.
+/// Jobs are kept in the database, so unfinished ones are run again after a restart.
+#[derive(Clone, Debug, Serialize)]
+pub struct Job {
+    pub id: DbId,
+    pub source_id: DbId,
+    pub list_id: DbId,
+    pub user_id: DbId,
+    pub scheduled: bool, // started by the scheduler, not by a user
+    pub state: JobState,
+    pub rows_processed: usize,
+    pub stats: Option<UpdateStats>,
+    pub error: Option<String>,
+    pub created: String,
+    pub modified: String,
+
+    #[serde(skip_serializing)]
+    pub app: Arc<AppState>,
+}
+
+impl Job {
+    const SELECT: &'static str = r#"SELECT id,source_id,list_id,user_id,scheduled,state,rows_processed,stats,error,created,modified FROM `job`"#;
+
+    fn from_row(app: &Arc<AppState>, row: &mysql_async::Row) -> Option<Self> {
+        let state: String = row.get(5)?;
+        let stats: Option<String> = row.get(7)?;
+        Some(Self {
+            id: row.get(0)?,
+            source_id: row.get(1)?,
+            list_id: row.get(2)?,
+            user_id: row.get(3)?,
+            scheduled: row.get(4)?,
+            state: JobState::new(&state)?,
+            rows_processed: row.get(6)?,
+            stats: stats.and_then(|stats| serde_json::from_str(&stats).ok()),
+            error: row.get(8)?,
+            created: Row::get_timestamp_from_row(&row.get(9)?),
+            modified: Row::get_timestamp_from_row(&row.get(10)?),
+            app: app.clone(),
+        })
+    }
+
+    pub async fn from_db(app: &Arc<AppState>, job_id: DbId) -> Option<Self> {
+        let sql = format!("{} WHERE id=:job_id", Self::SELECT);
+        app.get_gulp_conn()
+            .await
+            .ok()?
+            .exec_iter(sql, params! {job_id})
+            .await
+            .ok()?
+            .map_and_drop(|row| Self::from_row(app, &row))
+            .await
+            .ok()?
+            .into_iter()
+            .flatten()
+            .next()
+    }
+
+    /// The most recent jobs for a data source, newest first
+    pub async fn for_source(
+        app: &Arc<AppState>,
+        source_id: DbId,
+        limit: usize,
+    ) -> Result<Vec<Self>, GulpError> {
+        let sql = format!(
+            "{} WHERE source_id=:source_id ORDER BY id DESC LIMIT {limit}",
+            Self::SELECT
+        );
+        let jobs = app
+            .get_gulp_conn()
+            .await?
+            .exec_iter(sql, params! {source_id})
+            .await?
+            .map_and_drop(|row| Self::from_row(app, &row))
+            .await?;
+        Ok(jobs.into_iter().flatten().collect())
+    }
+
+    /// Queues an update of the list of `source`, and wakes up the worker
+    pub async fn create(
+        app: &Arc<AppState>,
+        source: &DataSource,
+        user_id: DbId,
+        scheduled: bool,
+    ) -> Result<Self, GulpError> {
+        let source_id = source.id;
+        let list_id = source.list_id;
+        let state = JobState::QUEUED.to_string();
+        let sql = "INSERT INTO `job` (source_id,list_id,user_id,scheduled,state,rows_processed,created,modified) VALUES (:source_id,:list_id,:user_id,:scheduled,:state,0,now(),now())";
+        let mut conn = app.get_gulp_conn().await?;
+        conn.exec_drop(sql, params! {source_id,list_id,user_id,scheduled,state})
+            .await?;
+        let job_id = conn.last_insert_id().ok_or("Could not create job")?;
+        drop(conn);
+        let job = Self::from_db(app, job_id)
+            .await
+            .ok_or_else(|| format!("Could not load job #{job_id}"))?;
+        app.job_notify.notify_one();
+        Ok(job)
+    }
+
+    pub async fn set_state(&self, state: JobState) -> Result<(), GulpError> {
+        let job_id = self.id;
+        let state = state.to_string();
+        let sql = "UPDATE `job` SET state=:state,modified=now() WHERE id=:job_id";
+        self.app
+            .get_gulp_conn()
+            .await?
+            .exec_drop(sql, params! {job_id,state})
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_rows_processed(&self, rows_processed: usize) -> Result<(), GulpError> {
+        let job_id = self.id;
+        let sql = "UPDATE `job` SET rows_processed=:rows_processed,modified=now() WHERE id=:job_id";
+        self.app
+            .get_gulp_conn()
+            .await?
+            .exec_drop(sql, params! {job_id,rows_processed})
+            .await?;
+        Ok(())
+    }
+
+    async fn finish(&self, result: &Result<UpdateStats, GulpError>) -> Result<(), GulpError> {
+        let job_id = self.id;
+        let (state, stats, error) = match result {
+            Ok(stats) => (JobState::DONE, Some(serde_json::to_string(stats)?), None),
+            Err(e) => (JobState::FAILED, None, Some(e.to_string())),
+        };
+        let state = state.to_string();
+        let sql =
+            "UPDATE `job` SET state=:state,stats=:stats,error=:error,modified=now() WHERE id=:job_id";
+        self.app
+            .get_gulp_conn()
+            .await?
+            .exec_drop(sql, params! {job_id,state,stats,error})
+            .await?;
+        Ok(())
+    }
+
+    async fn run(&self) {
+        let result = self.update_list().await;
+        if let Err(e) = self.finish(&result).await {
+            tracing::error!("Could not finish job #{}: {e}", self.id);
+        }
+    }
+
+    async fn update_list(&self) -> Result<UpdateStats, GulpError> {
+        let source = DataSource::from_db(&self.app, self.source_id)
+            .await
+            .ok_or_else(|| format!("No source #{}", self.source_id))?;
+        let list = AppState::get_list(&self.app, self.list_id)
+            .await
+            .ok_or_else(|| format!("No list #{}", self.list_id))?;
+        // Work on a copy, so the list is not locked while the job runs. The job starts under
+        // the lock, so changes to the list that check `is_running_for_list` under the lock
+        // either finish before the job starts, or are refused until it is done.
+        let list_copy = {
+            let list = list.lock().await;
+            self.set_state(JobState::DOWNLOADING).await?;
+            list.clone()
+        };
+        let stats = list_copy
+            .update_from_source(&source, self.user_id, Some(self))
+            .await?;
+        if self.scheduled && source.auto_snapshot {
+            list.lock().await.snapshot().await?;
+        }
+        Ok(stats)
+    }
+
+    /// Whether a job for the source is queued or running
+    pub async fn has_unfinished(app: &Arc<AppState>, source_id: DbId) -> Result<bool, GulpError> {
+        let states = Self::sql_states(&[
+            JobState::QUEUED,
+            JobState::DOWNLOADING,
+            JobState::PARSING,
+            JobState::INSERTING,
+        ]);
+        let sql = format!(
+            "SELECT count(*) FROM `job` WHERE source_id=:source_id AND state IN ({states})"
+        );
+        let count: Vec<usize> = app
+            .get_gulp_conn()
+            .await?
+            .exec_iter(sql, params! {source_id})
+            .await?
+            .map_and_drop(mysql_async::from_row::<usize>)
+            .await?;
+        Ok(count.first().is_some_and(|count| *count > 0))
+    }
+
+    /// Whether a job is updating the list right now. The job writes to the current revision
+    /// of a copy of the list, so snapshots and other changes have to wait until it is done.
+    pub async fn is_running_for_list(
+        app: &Arc<AppState>,
+        list_id: DbId,
+    ) -> Result<bool, GulpError> {
+        let states = Self::sql_states(&[
+            JobState::DOWNLOADING,
+            JobState::PARSING,
+            JobState::INSERTING,
+        ]);
+        let sql =
+            format!("SELECT count(*) FROM `job` WHERE list_id=:list_id AND state IN ({states})");
+        let count: Vec<usize> = app
+            .get_gulp_conn()
+            .await?
+            .exec_iter(sql, params! {list_id})
+            .await?
+            .map_and_drop(mysql_async::from_row::<usize>)
+            .await?;
+        Ok(count.first().is_some_and(|count| *count > 0))
+    }
+
+    /// Unix timestamp of the start of the last scheduled job for the source, if any
+    pub async fn last_scheduled_run(
+        app: &Arc<AppState>,
+        source_id: DbId,
+    ) -> Result<Option<i64>, GulpError> {
+        let sql = "SELECT UNIX_TIMESTAMP(max(created)) FROM `job` WHERE source_id=:source_id AND scheduled=1";
+        let timestamps: Vec<Option<i64>> = app
+            .get_gulp_conn()
+            .await?
+            .exec_iter(sql, params! {source_id})
+            .await?
+            .map_and_drop(mysql_async::from_row::<Option<i64>>)
+            .await?;
+        Ok(timestamps.into_iter().flatten().next())
+    }
+
+    fn sql_states(states: &[JobState]) -> String {
+        states
+            .iter()
+            .map(|state| format!("'{state}'"))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    async fn next_queued(app: &Arc<AppState>) -> Result<Option<Self>, GulpError> {
+        let state = JobState::QUEUED.to_string();
+        let sql = format!("{} WHERE state=:state ORDER BY id LIMIT 1", Self::SELECT);
+        let jobs = app
+            .get_gulp_conn()
+            .await?
+            .exec_iter(sql, params! {state})
+            .await?
+            .map_and_drop(|row| Self::from_row(app, &row))
+            .await?;
+        Ok(jobs.into_iter().flatten().next())
+    }
+
+    /// Jobs that were running when the server stopped are queued again
+    async fn requeue_interrupted(app: &Arc<AppState>) -> Result<(), GulpError> {
+        let queued = JobState::QUEUED.to_string();
+        let states = Self::sql_states(&[
+            JobState::DOWNLOADING,
+            JobState::PARSING,
+            JobState::INSERTING,
+        ]);
+        let sql =
+            format!("UPDATE `job` SET state=:queued,modified=now() WHERE state IN ({states})");
+        app.get_gulp_conn()
+            .await?
+            .exec_drop(sql, params! {queued})
+            .await?;
+        Ok(())
+    }
+}
+
+/// Runs queued jobs one after the other, so two updates never write to a list at the same time
+pub async fn run_worker(app: Arc<AppState>) {
+    if let Err(e) = Job::requeue_interrupted(&app).await {
+        tracing::error!("Could not queue interrupted jobs: {e}");
+    }
+    loop {
+        match Job::next_queued(&app).await {
+            Ok(Some(job)) => {
+                job.run().await;
+                continue;
+            }
+            Ok(None) => {}
+            Err(e) => tracing::error!("Could not get next job: {e}"),
+        }
+        let _ = tokio::time::timeout(JOB_POLL_INTERVAL, app.job_notify.notified()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_state() {
+        for state in [
+            JobState::QUEUED,
+            JobState::DOWNLOADING,
+            JobState::PARSING,
+            JobState::INSERTING,
+            JobState::DONE,
+            JobState::FAILED,
+        ] {
+            assert_eq!(JobState::new(&state.to_string()), Some(state));
+        }
+        assert_eq!(JobState::new("done"), Some(JobState::DONE));
+        assert_eq!(JobState::new("RUNNING"), None);
+    }
+}
@@ -1,18 +1,119 @@
 use crate::app_state::AppState;
 use crate::cell::*;
-use crate::data_source::{CellSet, DataSource};
+use crate::data_source::{DataSource, DataSourceUpdateMode};
+use crate::diff::RevisionDiff;
 use crate::header::*;
+use crate::job::{Job, JobState};
 use crate::row::*;
+use crate::row_filter::RowFilter;
+use crate::row_sort::RowSort;
 use crate::GulpError;
-use mysql_async::{prelude::*, Conn};
-use serde::Serialize;
+use mysql_async::{prelude::*, Conn, Transaction};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::sync::Arc;
 
 const ROW_INSERT_BATCH_SIZE: usize = 1000;
 
+/// What an update from a data source did to the list
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct UpdateStats {
+    pub added: usize,
+    pub changed: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+    pub skipped: usize,
+    #[serde(default)]
+    pub source_unchanged: bool, // the source content was imported before, so nothing was done
+}
+
+/// Matches incoming rows to the existing rows of a list by key; the part of a sync
+/// that does not need the database. The first existing row with a key is kept for
+/// it, other rows with the same key are removed when an incoming row has the key.
+struct RowSync {
+    existing: HashMap<Vec<String>, Vec<(DbId, String)>>, // key => (row_num, json), by row_num
+    seen: HashSet<Vec<String>>,
+    next_row_num: DbId,
+    stats: UpdateStats,
+}
+
+impl RowSync {
+    /// `existing` are (key, row_num, json) of the rows of the current revision, ordered by row_num
+    fn new(
+        existing: impl Iterator<Item = (Vec<String>, DbId, String)>,
+        next_row_num: DbId,
+    ) -> Self {
+        let mut by_key: HashMap<Vec<String>, Vec<(DbId, String)>> = HashMap::new();
+        for (key, row_num, json) in existing {
+            by_key.entry(key).or_default().push((row_num, json));
+        }
+        Self {
+            existing: by_key,
+            seen: HashSet::new(),
+            next_row_num,
+            stats: UpdateStats::default(),
+        }
+    }
+
+    /// Returns the `row_num` to write an incoming row to (None if unchanged or skipped),
+    /// and the row numbers of other rows with the same key, to be removed.
+    /// Fails if an incoming row before had the same key.
+    fn add(
+        &mut self,
+        key: Option<Vec<String>>,
+        json: &str,
+    ) -> Result<(Option<DbId>, Vec<DbId>), GulpError> {
+        let key = match key {
+            Some(key) => key,
+            None => {
+                self.stats.skipped += 1;
+                return Ok((None, vec![]));
+            }
+        };
+        if !self.seen.insert(key.to_owned()) {
+            return Err(format!("Duplicate key '{}' in the incoming rows", key.join("|")).into());
+        }
+        let (first, others) = match self.existing.get(&key).and_then(|rows| rows.split_first()) {
+            Some((first, others)) => (Some(first), others),
+            None => (None, &[][..]),
+        };
+        let remove: Vec<DbId> = others.iter().map(|(row_num, _)| *row_num).collect();
+        self.stats.removed += remove.len();
+        let row_num = match first {
+            Some((_, old_json)) if old_json == json => {
+                self.stats.unchanged += 1;
+                None
+            }
+            Some((row_num, _)) => {
+                self.stats.changed += 1;
+                Some(*row_num)
+            }
+            None => {
+                self.stats.added += 1;
+                self.next_row_num += 1;
+                Some(self.next_row_num - 1)
+            }
+        };
+        Ok((row_num, remove))
+    }
+
+    /// Row numbers of the existing rows whose key was not in the incoming rows
+    fn remove_missing(&mut self) -> Vec<DbId> {
+        let mut remove: Vec<DbId> = self
+            .existing
+            .iter()
+            .filter(|(key, _rows)| !self.seen.contains(*key))
+            .flat_map(|(_key, rows)| rows.iter().map(|(row_num, _)| *row_num))
+            .collect();
+        remove.sort();
+        self.stats.removed += remove.len();
+        remove
+    }
+}
+
 #[derive(Clone, Debug, Serialize)]
 pub struct List {
     pub id: DbId,
@@ -60,10 +161,44 @@ impl List {
             schema: header_schema,
         };
         let _ = header.create_in_db(&self.app).await?;
+        let key_columns_changed = header.schema.key_columns != self.header.schema.key_columns;
         self.header = header;
+        if key_columns_changed {
+            self.update_key_md5s().await?;
+        }
         Ok(())
     }
 
+    /// Stores the key of each row of the current revision, for `Row::check_unique_key`.
+    /// Keys are written with the rows; this is needed when the key columns change.
+    pub async fn update_key_md5s(&self) -> Result<(), GulpError> {
+        let mut conn = self.app.get_gulp_conn().await?;
+        let sql = "UPDATE `row` SET key_md5=:key_md5 WHERE id=:id";
+        let mut after_row_num = 0;
+        loop {
+            let rows = self
+                .get_rows_for_revision_after(
+                    self.revision_id,
+                    after_row_num,
+                    ROW_INSERT_BATCH_SIZE as DbId,
+                )
+                .await?;
+            let params: Vec<mysql_async::Params> = rows
+                .iter()
+                .map(|row| {
+                    let id = row.id;
+                    let key_md5 = self.header.schema.get_key_md5(&row.cells);
+                    params! {id,key_md5}
+                })
+                .collect();
+            conn.exec_batch(sql, params.iter()).await?;
+            match rows.last() {
+                Some(row) if rows.len() >= ROW_INSERT_BATCH_SIZE => after_row_num = row.row_num,
+                _ => return Ok(()),
+            }
+        }
+    }
+
     pub async fn add_access(
         &self,
         app: &Arc<AppState>,
@@ -121,6 +256,24 @@ impl List {
         }
     }
 
+    /// Key/value pairs describing an export, for the spreadsheet "Metadata" sheets
+    pub fn get_export_metadata(
+        &self,
+        revision_id: DbId,
+        rows: usize,
+    ) -> Vec<(&'static str, String)> {
+        let now = chrono::Utc::now()
+            .format("%Y-%m-%d %H:%M:%S UTC")
+            .to_string();
+        vec![
+            ("List", self.name.to_owned()),
+            ("List ID", format!("{}", self.id)),
+            ("Revision", format!("{revision_id}")),
+            ("Rows", format!("{rows}")),
+            ("Exported", now),
+        ]
+    }
+
     pub async fn get_rows_for_revision(&self, revision_id: DbId) -> Result<Vec<Row>, GulpError> {
         self.get_rows_for_revision_paginated(revision_id, 0, None)
             .await
@@ -137,7 +290,7 @@ impl List {
         let sql = r#"SELECT row.id,list_id,row_num,revision_id,json,json_md5,user_id,modified
             FROM `row`
             WHERE revision_id=(SELECT max(revision_id) FROM `row` i WHERE i.row_num = row.row_num AND i.list_id=:list_id AND revision_id<=:revision_id)
-            AND list_id=:list_id AND revision_id<=:revision_id
+            AND list_id=:list_id AND revision_id<=:revision_id AND json!='null'
             ORDER BY row_num
             LIMIT :length OFFSET :start"#;
         let row_opts = self
@@ -152,13 +305,280 @@ impl List {
         Ok(rows)
     }
 
+    /// Up to `length` rows of a revision with a row number above `after_row_num`, in order.
+    /// Unlike an OFFSET, this stays fast when reading a large list chunk by chunk.
+    pub async fn get_rows_for_revision_after(
+        &self,
+        revision_id: DbId,
+        after_row_num: DbId,
+        length: DbId,
+    ) -> Result<Vec<Row>, GulpError> {
+        let list_id = self.id;
+        let sql = r#"SELECT row.id,list_id,row_num,revision_id,json,json_md5,user_id,modified
+            FROM `row`
+            WHERE revision_id=(SELECT max(revision_id) FROM `row` i WHERE i.row_num = row.row_num AND i.list_id=:list_id AND revision_id<=:revision_id)
+            AND list_id=:list_id AND revision_id<=:revision_id AND json!='null' AND row_num>:after_row_num
+            ORDER BY row_num
+            LIMIT :length"#;
+        let row_opts = self
+            .app
+            .get_gulp_conn()
+            .await?
+            .exec_iter(sql, params! {list_id,revision_id,after_row_num,length})
+            .await?
+            .map_and_drop(|row| Row::from_row(&row, &self.header))
+            .await?;
+        Ok(row_opts.into_iter().flatten().collect())
+    }
+
+    /// Like `get_rows_for_revision_paginated`, but only returns rows matching `filter`,
+    /// optionally sorted. Also returns the total number of matching rows.
+    /// Filtering and sorting happen in MySQL where possible; rows for the rest of the
+    /// filter are read one by one, keeping only the requested ones.
+    pub async fn get_filtered_rows_for_revision(
+        &self,
+        revision_id: DbId,
+        filter: &RowFilter,
+        sort: &Option<RowSort>,
+        start: DbId,
+        length: Option<DbId>,
+    ) -> Result<(Vec<Row>, usize), GulpError> {
+        if filter.is_empty() && sort.is_none() {
+            let total = self.get_rows_in_revision(revision_id).await?;
+            let rows = self
+                .get_rows_for_revision_paginated(revision_id, start, length)
+                .await?;
+            return Ok((rows, total));
+        }
+        let (sql_filter, filter) = filter.split_sql(&self.header);
+        let order_by = match sort {
+            Some(sort) => sort.as_sql(&self.header),
+            None => "row_num".to_string(),
+        };
+        let conditions = format!(
+            r#"FROM `row`
+            WHERE revision_id=(SELECT max(revision_id) FROM `row` i WHERE i.row_num = row.row_num AND i.list_id=:list_id AND revision_id<=:revision_id)
+            AND list_id=:list_id AND revision_id<=:revision_id AND json!='null'{}"#,
+            sql_filter.as_sql()
+        );
+        let mut params: Vec<(String, mysql_async::Value)> = sql_filter
+            .params
+            .into_iter()
+            .map(|(name, value)| (name, value.into()))
+            .collect();
+        params.push(("list_id".to_string(), self.id.into()));
+        params.push(("revision_id".to_string(), revision_id.into()));
+        let length = length.unwrap_or(DbId::MAX);
+        let mut conn = self.app.get_gulp_conn().await?;
+
+        if filter.is_empty() {
+            let sql = format!("SELECT count(*) {conditions}");
+            let total = conn
+                .exec_iter(sql, params.to_owned())
+                .await?
+                .map_and_drop(mysql_async::from_row::<usize>)
+                .await?
+                .first()
+                .cloned()
+                .unwrap_or(0);
+            let sql = format!(
+                "SELECT row.id,list_id,row_num,revision_id,json,json_md5,user_id,modified {conditions}
+                ORDER BY {order_by}
+                LIMIT :length OFFSET :start"
+            );
+            params.push(("length".to_string(), length.into()));
+            params.push(("start".to_string(), start.into()));
+            let rows = conn
+                .exec_iter(sql, params)
+                .await?
+                .map_and_drop(|row| Row::from_row(&row, &self.header))
+                .await?;
+            return Ok((rows.into_iter().flatten().collect(), total));
+        }
+
+        let sql = format!(
+            "SELECT row.id,list_id,row_num,revision_id,json,json_md5,user_id,modified {conditions}
+            ORDER BY {order_by}"
+        );
+        let end = start.saturating_add(length);
+        let mut total: DbId = 0;
+        let mut rows = vec![];
+        conn.exec_iter(sql, params)
+            .await?
+            .for_each(|row| {
+                let row = match Row::from_row(&row, &self.header) {
+                    Some(row) => row,
+                    None => return,
+                };
+                if !filter.matches(&row, &self.header) {
+                    return;
+                }
+                if total >= start && total < end {
+                    rows.push(row);
+                }
+                total += 1;
+            })
+            .await?;
+        Ok((rows, total as usize))
+    }
+
+    /// Writes the rows and header of an earlier revision as the state of the current revision.
+    /// History is preserved; rows are changed (or deleted) like any other edit.
+    pub async fn revert_to_revision(
+        &mut self,
+        revision_id: DbId,
+        user_id: DbId,
+    ) -> Result<UpdateStats, GulpError> {
+        if revision_id >= self.revision_id {
+            return Err(format!(
+                "Can only revert to a revision before the current one ({})",
+                self.revision_id
+            )
+            .into());
+        }
+
+        let mut conn = self.app.get_gulp_conn().await?;
+        let old_header = Header::from_list_revision_id(&mut conn, self.id, revision_id)
+            .await
+            .ok_or_else(|| format!("No header for revision {revision_id}"))?;
+        if old_header.schema.id != self.header.schema.id {
+            self.set_header_schema(old_header.schema).await?;
+        }
+
+        let old_rows: HashMap<DbId, Row> = self
+            .get_rows_for_revision(revision_id)
+            .await?
+            .into_iter()
+            .map(|row| (row.row_num, row))
+            .collect();
+        let current_rows = self.get_rows_for_revision(self.revision_id).await?;
+        let mut transaction = Self::start_row_transaction(&mut conn).await?;
+        let mut stats = UpdateStats::default();
+        let mut rows = vec![];
+        let mut current_row_nums = HashSet::new();
+        for row in current_rows {
+            current_row_nums.insert(row.row_num);
+            let mut new_row = match old_rows.get(&row.row_num) {
+                Some(old_row) if old_row.json == row.json => {
+                    stats.unchanged += 1;
+                    continue;
+                }
+                Some(old_row) => {
+                    stats.changed += 1;
+                    old_row.to_owned()
+                }
+                None => {
+                    stats.removed += 1;
+                    Row::tombstone(self.id, row.row_num, self.revision_id, user_id)
+                }
+            };
+            new_row.revision_id = self.revision_id;
+            new_row.user_id = user_id;
+            rows.push(new_row);
+            if rows.len() >= ROW_INSERT_BATCH_SIZE {
+                self.flush_row_replace(&mut transaction, &mut rows).await?;
+            }
+        }
+        for (row_num, old_row) in old_rows {
+            if current_row_nums.contains(&row_num) {
+                continue;
+            }
+            // Row was deleted after the old revision
+            stats.added += 1;
+            let mut new_row = old_row;
+            new_row.revision_id = self.revision_id;
+            new_row.user_id = user_id;
+            rows.push(new_row);
+            if rows.len() >= ROW_INSERT_BATCH_SIZE {
+                self.flush_row_replace(&mut transaction, &mut rows).await?;
+            }
+        }
+        self.flush_row_replace(&mut transaction, &mut rows).await?;
+        transaction.commit().await?;
+        Ok(stats)
+    }
+
+    /// Returns all stored versions of a row, oldest first, each rendered with the header
+    /// that was valid at its revision. Deleted versions have `"deleted":true` and no cells.
+    pub async fn get_row_history(
+        &self,
+        row_num: DbId,
+    ) -> Result<Vec<serde_json::Value>, GulpError> {
+        let sql = r#"SELECT row.id,list_id,row_num,revision_id,json,json_md5,user_id,modified
+            FROM `row`
+            WHERE list_id=:list_id AND row_num=:row_num
+            ORDER BY revision_id"#;
+        let list_id = self.id;
+        let mut conn = self.app.get_gulp_conn().await?;
+        let db_rows = conn
+            .exec_iter(sql, params! {list_id,row_num})
+            .await?
+            .map_and_drop(|row| row)
+            .await?;
+        let mut headers: HashMap<DbId, Header> = HashMap::new();
+        let mut ret = vec![];
+        for db_row in db_rows {
+            let revision_id: DbId = db_row.get(3).ok_or("get_row_history: no revision_id")?;
+            if let Entry::Vacant(entry) = headers.entry(revision_id) {
+                let header = Header::from_list_revision_id(&mut conn, list_id, revision_id)
+                    .await
+                    .ok_or_else(|| format!("No header for revision {revision_id}"))?;
+                entry.insert(header);
+            }
+            let header = &headers[&revision_id];
+            let row = Row::from_row(&db_row, header).ok_or("get_row_history: bad row")?;
+            let mut j = row.as_json(header);
+            j["revision_id"] = json!(revision_id);
+            j["deleted"] = json!(row.is_deleted());
+            ret.push(j);
+        }
+        Ok(ret)
+    }
+
+    /// Returns the rows that were added, removed, or modified between two revisions
+    pub async fn diff_revisions(&self, from: DbId, to: DbId) -> Result<RevisionDiff, GulpError> {
+        let rows_from = self.get_rows_for_revision(from).await?;
+        let rows_to = self.get_rows_for_revision(to).await?;
+        let deleted_by = self.get_deleted_rows(from, to).await?;
+        Ok(RevisionDiff::new(
+            from,
+            to,
+            &rows_from,
+            &rows_to,
+            &deleted_by,
+        ))
+    }
+
+    /// Returns row_num => user_id for rows deleted after revision `from`, up to revision `to`
+    async fn get_deleted_rows(
+        &self,
+        from: DbId,
+        to: DbId,
+    ) -> Result<HashMap<DbId, DbId>, GulpError> {
+        let sql = r#"SELECT row_num,user_id FROM `row`
+            WHERE revision_id=(SELECT max(revision_id) FROM `row` i WHERE i.row_num = row.row_num AND i.list_id=:list_id AND revision_id<=:to)
+            AND list_id=:list_id AND revision_id>:from AND revision_id<=:to AND json='null'"#;
+        let list_id = self.id;
+        let ret = self
+            .app
+            .get_gulp_conn()
+            .await?
+            .exec_iter(sql, params! {list_id,from,to})
+            .await?
+            .map_and_drop(mysql_async::from_row::<(DbId, DbId)>)
+            .await?
+            .into_iter()
+            .collect();
+        Ok(ret)
+    }
+
     pub async fn get_users_in_revision(
         &self,
         revision_id: DbId,
     ) -> Result<HashMap<DbId, String>, GulpError> {
         let sql = r#"SELECT DISTINCT user_id,user.name FROM `row`,`user`
             WHERE revision_id=(SELECT max(revision_id) FROM `row` i WHERE i.row_num = row.row_num AND i.list_id=:list_id AND revision_id<=:revision_id)
-            AND list_id=:list_id AND revision_id<=:revision_id AND user_id=user.id"#;
+            AND list_id=:list_id AND revision_id<=:revision_id AND json!='null' AND user_id=user.id"#;
         let list_id = self.id;
         let ret = self
             .app
@@ -199,7 +619,7 @@ impl List {
     pub async fn get_rows_in_revision(&self, revision_id: DbId) -> Result<usize, GulpError> {
         let sql = r#"SELECT count(*) FROM `row`
             WHERE revision_id=(SELECT max(revision_id) FROM `row` i WHERE i.row_num = row.row_num AND i.list_id=:list_id AND revision_id<=:revision_id)
-            AND list_id=:list_id AND revision_id<=:revision_id"#;
+            AND list_id=:list_id AND revision_id<=:revision_id AND json!='null'"#;
         let list_id = self.id;
         let row_number = self
             .app
@@ -215,16 +635,26 @@ impl List {
         Ok(row_number)
     }
 
+    /// Returns the IDs in all `WikidataEntity` cells of the given rows
+    pub fn get_wikidata_entity_ids(&self, rows: &[Row]) -> Vec<String> {
+        let mut ids: Vec<String> = rows
+            .iter()
+            .flat_map(|row| row.cells.iter())
+            .filter_map(|cell| match cell {
+                Some(Cell::WikidataEntity(id)) => Some(id.to_owned()),
+                _ => None,
+            })
+            .collect();
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+
     pub fn get_client() -> Result<reqwest::Client, GulpError> {
         let client = reqwest::Client::builder().user_agent("gulp/0.1").build()?;
         Ok(client)
     }
 
-    pub fn get_text_from_url(url: &str) -> Result<String, GulpError> {
-        let agent = ureq::AgentBuilder::new().build();
-        Ok(agent.get(url).call()?.into_string()?)
-    }
-
     async fn load_json_md5s(&self, conn: &mut Conn) -> Result<HashSet<String>, GulpError> {
         let list_id = self.id;
         let sql = r#"SELECT json_md5 FROM `row`
@@ -239,10 +669,40 @@ impl List {
         Ok(ret)
     }
 
+    /// Row changes that are written in batches go into one transaction, so a failure
+    /// halfway through leaves the list unchanged
+    async fn start_row_transaction(conn: &mut Conn) -> Result<Transaction<'_>, GulpError> {
+        let tx_opts = mysql_async::TxOpts::default()
+            .with_consistent_snapshot(true)
+            .with_isolation_level(mysql_async::IsolationLevel::RepeatableRead)
+            .to_owned();
+        Ok(conn.start_transaction(tx_opts).await?)
+    }
+
+    /// Writes new rows
     async fn flush_row_insert(
         &self,
-        conn: &mut Conn,
+        transaction: &mut Transaction<'_>,
+        rows: &mut Vec<Row>,
+    ) -> Result<(), GulpError> {
+        self.flush_rows(transaction, rows, "INSERT").await
+    }
+
+    /// Writes rows that might replace a version written earlier in the same revision,
+    /// e.g. when a sync or revert changes a row that was already edited
+    async fn flush_row_replace(
+        &self,
+        transaction: &mut Transaction<'_>,
+        rows: &mut Vec<Row>,
+    ) -> Result<(), GulpError> {
+        self.flush_rows(transaction, rows, "REPLACE").await
+    }
+
+    async fn flush_rows(
+        &self,
+        transaction: &mut Transaction<'_>,
         rows: &mut Vec<Row>,
+        statement: &str,
     ) -> Result<(), GulpError> {
         if rows.is_empty() {
             return Ok(());
@@ -256,24 +716,19 @@ impl List {
                 let json = &row.json;
                 let json_md5 = &row.json_md5;
                 let user_id = row.user_id;
-                params! {list_id,row_num,revision_id,json,json_md5,user_id}
+                let key_md5 = self.header.schema.get_key_md5(&row.cells);
+                params! {list_id,row_num,revision_id,json,json_md5,user_id,key_md5}
             })
             .collect();
-        let sql = r#"INSERT INTO `row` (list_id,row_num,revision_id,json,json_md5,user_id,modified) VALUES (:list_id,:row_num,:revision_id,:json,:json_md5,:user_id,now())"#;
-        let tx_opts = mysql_async::TxOpts::default()
-            .with_consistent_snapshot(true)
-            .with_isolation_level(mysql_async::IsolationLevel::RepeatableRead)
-            .to_owned();
-        let mut transaction = conn.start_transaction(tx_opts).await?;
+        let sql = format!("{statement} INTO `row` (list_id,row_num,revision_id,json,json_md5,user_id,key_md5,modified) VALUES (:list_id,:row_num,:revision_id,:json,:json_md5,:user_id,:key_md5,now())");
         transaction.exec_batch(sql, params.iter()).await?;
-        transaction.commit().await?;
         rows.clear();
         Ok(())
     }
 
     pub async fn get_sources(&self) -> Result<Vec<DataSource>, GulpError> {
         let list_id = self.id;
-        let sql = r#"SELECT id,list_id,source_type,source_format,location,user_id FROM data_source WHERE list_id=:list_id"#;
+        let sql = r#"SELECT id,list_id,source_type,source_format,location,user_id,update_mode,key_column,first_row_is_header,excel_sheet,excel_range,json_pointer,refresh_schedule,auto_snapshot,archive_member FROM data_source WHERE list_id=:list_id"#;
         let sources = self
             .app
             .get_gulp_conn()
@@ -324,7 +779,7 @@ impl List {
 
     async fn check_json_exists(
         &self,
-        _conn: &mut Conn,
+        _conn: &mut impl Queryable,
         _json_text: &str,
         _json_md5: &str,
     ) -> Result<bool, GulpError> {
@@ -332,14 +787,7 @@ impl List {
         Ok(true)
     }
 
-    async fn get_or_ignore_new_row(
-        &self,
-        conn: &mut Conn,
-        md5s: &HashSet<String>,
-        cells: Vec<Option<Cell>>,
-        row_num: DbId,
-        user_id: DbId,
-    ) -> Result<Option<Row>, GulpError> {
+    fn cells_as_json_text(&self, cells: &[Option<Cell>]) -> String {
         let cells2j: Vec<_> = cells
             .iter()
             .zip(self.header.schema.columns.iter())
@@ -351,7 +799,18 @@ impl List {
             .map(|cell| cell.unwrap_or_else(|| json!(null)))
             .collect();
         let cells_json = json! {cells2j};
-        let cells_json_text = cells_json.to_string();
+        cells_json.to_string()
+    }
+
+    async fn get_or_ignore_new_row(
+        &self,
+        conn: &mut impl Queryable,
+        md5s: &HashSet<String>,
+        cells: Vec<Option<Cell>>,
+        row_num: DbId,
+        user_id: DbId,
+    ) -> Result<Option<Row>, GulpError> {
+        let cells_json_text = self.cells_as_json_text(&cells);
         let json_md5 = Row::md5(&cells_json_text);
         let json_exists = if md5s.contains(&json_md5) {
             self.check_json_exists(conn, &cells_json_text, &json_md5)
@@ -378,49 +837,213 @@ impl List {
         Ok(None)
     }
 
+    /// Deletes a row from the current revision, by adding a tombstone.
+    pub async fn delete_row(&self, row_num: DbId, user_id: DbId) -> Result<(), GulpError> {
+        let mut conn = self.app.get_gulp_conn().await?;
+        let mut row = Row::from_db(&mut conn, self.id, row_num, self.revision_id, &self.header)
+            .await
+            .ok_or_else(|| format!("No row #{row_num} in list #{}", self.id))?;
+        if row.is_deleted() {
+            return Err(format!("Row #{row_num} in list #{} is already deleted", self.id).into());
+        }
+        row.revision_id = self.revision_id;
+        row.delete(&mut conn, user_id).await
+    }
+
+    /// Imports the rows of a data source; `job`, if given, is kept up to date with the progress.
     pub async fn update_from_source(
         &self,
         source: &DataSource,
         user_id: DbId,
+        job: Option<&Job>,
+    ) -> Result<UpdateStats, GulpError> {
+        Self::set_job_state(job, JobState::DOWNLOADING).await?;
+        let (file, cache) = source.fetch_file(&self.app, None).await?;
+        if cache.as_ref().is_some_and(|cache| cache.is_imported()) {
+            return Ok(UpdateStats {
+                source_unchanged: true,
+                ..Default::default()
+            });
+        }
+        let mut header_file = source.file_with_header(&self.app, file).await?;
+        // Read the values with the types of the list's columns, e.g. numbers from spreadsheets
+        header_file.headers = self.header.schema.columns.to_owned();
+        Self::set_job_state(job, JobState::PARSING).await?;
+        let rows = source.get_row_reader(&mut header_file)?;
+        Self::set_job_state(job, JobState::INSERTING).await?;
+        let stats = match source.update_mode {
+            DataSourceUpdateMode::APPEND => self.import_rows(rows, user_id, job).await?,
+            DataSourceUpdateMode::SYNC => {
+                // The key column of the source takes precedence over the key columns of the list
+                let key_columns = match source.key_column {
+                    Some(key_column) => vec![key_column],
+                    None => self.header.schema.key_columns.to_owned(),
+                };
+                if key_columns.is_empty() {
+                    return Err("Data source is in SYNC mode but has no key column".into());
+                }
+                self.sync_rows(rows, &key_columns, user_id, true, job)
+                    .await?
+            }
+        };
+        if let Some(cache) = cache {
+            cache.set_imported(&self.app).await?;
+        }
+        Ok(stats)
+    }
+
+    async fn set_job_state(job: Option<&Job>, state: JobState) -> Result<(), GulpError> {
+        match job {
+            Some(job) => job.set_state(state).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Records the number of incoming rows processed so far, once per batch
+    // `usize::is_multiple_of` needs Rust 1.87
+    #[allow(unknown_lints, clippy::manual_is_multiple_of)]
+    async fn report_progress(
+        job: Option<&Job>,
+        rows_processed: usize,
+        is_final: bool,
     ) -> Result<(), GulpError> {
-        let cell_set = source.get_cells(None).await?;
-        self.import_cells(&cell_set, user_id).await?;
-        Ok(())
+        match job {
+            Some(job) if is_final || rows_processed % ROW_INSERT_BATCH_SIZE == 0 => {
+                job.set_rows_processed(rows_processed).await
+            }
+            _ => Ok(()),
+        }
     }
 
-    async fn import_cells(&self, cell_set: &CellSet, user_id: DbId) -> Result<(), GulpError> {
-        // TODO delete rows?
+    /// Matches incoming rows to the current revision, using `key_columns` to identify rows.
+    /// Changed rows keep their `row_num`; with `remove_missing`, rows missing from the
+    /// incoming ones get a tombstone. Incoming rows without a key are skipped; two incoming
+    /// rows with the same key are an error. See `RowSync` for existing rows with the same key.
+    /// Rows are read and written in batches, not all at once, in one transaction.
+    async fn sync_rows(
+        &self,
+        incoming: impl Iterator<Item = Result<Row, GulpError>> + Send,
+        key_columns: &[usize],
+        user_id: DbId,
+        remove_missing: bool,
+        job: Option<&Job>,
+    ) -> Result<UpdateStats, GulpError> {
+        let schema = &self.header.schema;
+        if let Some(key_column) = key_columns.iter().find(|num| **num >= schema.columns.len()) {
+            return Err(
+                format!("Key column {key_column} does not exist in list {}", self.id).into(),
+            );
+        }
+        let existing = self
+            .get_rows_for_revision(self.revision_id)
+            .await?
+            .into_iter()
+            .filter_map(|row| {
+                let key = schema.get_key_for_columns(&row.cells, key_columns)?;
+                Some((key, row.row_num, row.json))
+            });
+
+        let mut conn = self.app.get_gulp_conn().await?;
+        let next_row_num = self.get_max_row_num(&mut conn).await? + 1;
+        let mut sync = RowSync::new(existing, next_row_num);
+        let mut transaction = Self::start_row_transaction(&mut conn).await?;
+        let mut rows = vec![];
+        let mut rows_processed = 0;
+        for row in incoming {
+            let row = row?;
+            rows_processed += 1;
+            Self::report_progress(job, rows_processed, false).await?;
+            let key = schema.get_key_for_columns(&row.cells, key_columns);
+            let json = self.cells_as_json_text(&row.cells);
+            let (row_num, remove) = sync.add(key, &json)?;
+            for row_num in remove {
+                rows.push(Row::tombstone(self.id, row_num, self.revision_id, user_id));
+            }
+            if let Some(row_num) = row_num {
+                rows.push(Row {
+                    id: 0,
+                    list_id: self.id,
+                    row_num,
+                    revision_id: self.revision_id,
+                    json_md5: Row::md5(&json),
+                    json,
+                    user_id,
+                    modified: String::new(),
+                    cells: row.cells,
+                });
+            }
+            if rows.len() >= ROW_INSERT_BATCH_SIZE {
+                self.flush_row_replace(&mut transaction, &mut rows).await?;
+            }
+        }
+        Self::report_progress(job, rows_processed, true).await?;
+
+        if remove_missing {
+            for row_num in sync.remove_missing() {
+                rows.push(Row::tombstone(self.id, row_num, self.revision_id, user_id));
+                if rows.len() >= ROW_INSERT_BATCH_SIZE {
+                    self.flush_row_replace(&mut transaction, &mut rows).await?;
+                }
+            }
+        }
+        self.flush_row_replace(&mut transaction, &mut rows).await?;
+        transaction.commit().await?;
+        Ok(sync.stats)
+    }
+
+    /// Adds new rows, writing them in batches as they are read, in one transaction, so
+    /// an error in the incoming rows leaves the list unchanged. If the list has key columns,
+    /// rows with an existing key are merged into the existing row instead.
+    async fn import_rows(
+        &self,
+        incoming: impl Iterator<Item = Result<Row, GulpError>> + Send,
+        user_id: DbId,
+        job: Option<&Job>,
+    ) -> Result<UpdateStats, GulpError> {
+        let key_columns = &self.header.schema.key_columns;
+        if !key_columns.is_empty() {
+            return self
+                .sync_rows(incoming, key_columns, user_id, false, job)
+                .await;
+        }
+        let mut stats = UpdateStats::default();
         let mut conn = self.app.get_gulp_conn().await?;
         let mut md5s = self.load_json_md5s(&mut conn).await?;
         let mut next_row_num = self.get_max_row_num(&mut conn).await? + 1;
+        let mut transaction = Self::start_row_transaction(&mut conn).await?;
         let mut rows = vec![];
+        let mut rows_processed = 0;
 
-        for row in &cell_set.rows {
+        for row in incoming {
+            let row = row?;
+            rows_processed += 1;
+            Self::report_progress(job, rows_processed, false).await?;
             if let Some(row) = self
-                .get_or_ignore_new_row(
-                    &mut conn,
-                    &md5s,
-                    row.cells.to_owned(),
-                    next_row_num,
-                    user_id,
-                )
+                .get_or_ignore_new_row(&mut transaction, &md5s, row.cells, next_row_num, user_id)
                 .await?
             {
                 if row.cells.is_empty() {
                     continue;
                 }
                 next_row_num += 1;
+                stats.added += 1;
                 md5s.insert(row.json_md5.to_owned());
                 rows.push(row);
                 if rows.len() >= ROW_INSERT_BATCH_SIZE {
-                    self.flush_row_insert(&mut conn, &mut rows).await?;
+                    self.flush_row_insert(&mut transaction, &mut rows).await?;
                 }
+            } else {
+                stats.unchanged += 1;
             }
         }
-        self.flush_row_insert(&mut conn, &mut rows).await?;
-        Ok(())
+        self.flush_row_insert(&mut transaction, &mut rows).await?;
+        transaction.commit().await?;
+        Self::report_progress(job, rows_processed, true).await?;
+        Ok(stats)
     }
 
+    /// Deleted rows (tombstones) are included, so a new row never reuses
+    /// the `row_num` of a deleted one, and row histories stay unambiguous.
     async fn get_max_row_num(&self, conn: &mut Conn) -> Result<DbId, GulpError> {
         let list_id = self.id;
         let sql = r#"SELECT IFNULL(max(row_num),0) FROM `row` 
@@ -443,6 +1066,51 @@ mod tests {
     use super::*;
     use crate::app_state::*;
 
+    #[test]
+    fn test_row_sync() {
+        let key = |s: &str| Some(vec![s.to_string()]);
+        let existing = vec![
+            (vec!["a".to_string()], 1, r#"["a",1]"#.to_string()),
+            (vec!["b".to_string()], 2, r#"["b",2]"#.to_string()),
+            (vec!["c".to_string()], 3, r#"["c",3]"#.to_string()),
+            (vec!["a".to_string()], 4, r#"["a",4]"#.to_string()),
+            (vec!["d".to_string()], 5, r#"["d",5]"#.to_string()),
+        ];
+        let mut sync = RowSync::new(existing.into_iter(), 10);
+        // Changed; the second row with the same key is removed
+        assert_eq!(
+            sync.add(key("a"), r#"["a",9]"#).unwrap(),
+            (Some(1), vec![4])
+        );
+        // Unchanged
+        assert_eq!(sync.add(key("b"), r#"["b",2]"#).unwrap(), (None, vec![]));
+        // New
+        assert_eq!(
+            sync.add(key("e"), r#"["e",1]"#).unwrap(),
+            (Some(10), vec![])
+        );
+        assert_eq!(
+            sync.add(key("f"), r#"["f",1]"#).unwrap(),
+            (Some(11), vec![])
+        );
+        // No key
+        assert_eq!(sync.add(None, r#"[null,1]"#).unwrap(), (None, vec![]));
+        assert_eq!(sync.remove_missing(), vec![3, 5]);
+        let stats = &sync.stats;
+        assert_eq!(
+            (
+                stats.added,
+                stats.changed,
+                stats.unchanged,
+                stats.removed,
+                stats.skipped
+            ),
+            (2, 1, 1, 3, 1)
+        );
+        // A key seen before
+        assert!(sync.add(key("e"), r#"["e",2]"#).is_err());
+    }
+
     #[tokio::test]
     async fn test_from_id() {
         let app = AppState::from_config_file("config.json").expect("app creation failed");
@@ -453,4 +1121,249 @@ mod tests {
         println!("{:?}", list.header.schema.columns[0]);
         //assert_eq!(list.header.schema.columns[0],"File candidates Hessen");
     }
+
+    const TEST_USER_ID: DbId = 1;
+
+    /// A header schema with one `String` column; the label makes it new in the test database
+    async fn create_test_header_schema(app: &Arc<AppState>, label: &str) -> HeaderSchema {
+        let nanos = chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default();
+        let json = json!({"columns":[{"column_type":"String","label":format!("{label} {nanos}")}]});
+        let mut hs = HeaderSchema::from_name_json("", &json.to_string()).expect("from_name_json");
+        hs.create_in_db(app).await.expect("create_in_db");
+        hs
+    }
+
+    /// A new list in the test database
+    async fn create_test_list(app: &Arc<AppState>, name: &str) -> List {
+        let hs = create_test_header_schema(app, name).await;
+        List::create_new(app, name, hs.id)
+            .await
+            .expect("create_new")
+    }
+
+    fn string_rows(values: &[&str]) -> impl Iterator<Item = Result<Row, GulpError>> + Send {
+        let rows: Vec<_> = values
+            .iter()
+            .map(|value| Ok(Row::from_cells(vec![Some(Cell::String(value.to_string()))])))
+            .collect();
+        rows.into_iter()
+    }
+
+    fn row_values(rows: &[Row]) -> Vec<String> {
+        rows.iter()
+            .map(|row| match &row.cells[0] {
+                Some(Cell::String(s)) => s.to_owned(),
+                other => format!("{other:?}"),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_tombstones() {
+        let app = AppState::from_config_file("config.json").expect("app creation failed");
+        let app = Arc::new(app);
+        let mut list = create_test_list(&app, "test_tombstones").await;
+        list.import_rows(string_rows(&["a", "b", "c"]), TEST_USER_ID, None)
+            .await
+            .expect("import_rows");
+        let old_revision_id = list.revision_id;
+        let revision_id = list.snapshot().await.expect("snapshot");
+        assert_eq!(revision_id, old_revision_id + 1);
+        list.delete_row(2, TEST_USER_ID).await.expect("delete_row");
+
+        let rows = list.get_rows_for_revision(revision_id).await.unwrap();
+        assert_eq!(row_values(&rows), vec!["a", "c"]);
+        assert_eq!(list.get_rows_in_revision(revision_id).await.unwrap(), 2);
+        let mut conn = app.get_gulp_conn().await.unwrap();
+        // The latest version of the row is the tombstone
+        let row = Row::from_db(&mut conn, list.id, 2, revision_id, &list.header).await;
+        assert!(row.is_some_and(|row| row.is_deleted() && row.cells.is_empty()));
+        assert!(list.delete_row(2, TEST_USER_ID).await.is_err());
+
+        // Older revisions still have the row
+        let rows = list.get_rows_for_revision(old_revision_id).await.unwrap();
+        assert_eq!(row_values(&rows), vec!["a", "b", "c"]);
+        assert_eq!(list.get_rows_in_revision(old_revision_id).await.unwrap(), 3);
+
+        // The number of the deleted row is not used again
+        assert_eq!(list.get_max_row_num(&mut conn).await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_row_history() {
+        let app = AppState::from_config_file("config.json").expect("app creation failed");
+        let app = Arc::new(app);
+        let mut list = create_test_list(&app, "test_row_history").await;
+        list.import_rows(string_rows(&["a"]), TEST_USER_ID, None)
+            .await
+            .expect("import_rows");
+        let first_revision_id = list.revision_id;
+        let revision_id = list.snapshot().await.expect("snapshot");
+        let mut conn = app.get_gulp_conn().await.unwrap();
+        let mut row = Row::from_db(&mut conn, list.id, 1, revision_id, &list.header)
+            .await
+            .expect("row 1");
+        row.cells = vec![Some(Cell::String("b".to_string()))];
+        row.revision_id = revision_id;
+        row.add_or_replace(&list.header, &mut conn, TEST_USER_ID)
+            .await
+            .expect("add_or_replace");
+        let last_revision_id = list.snapshot().await.expect("snapshot");
+        list.delete_row(1, TEST_USER_ID).await.expect("delete_row");
+
+        let history = list.get_row_history(1).await.expect("get_row_history");
+        let history: Vec<_> = history
+            .iter()
+            .map(|j| {
+                (
+                    j["revision_id"].clone(),
+                    j["deleted"].clone(),
+                    j["c"].clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            history,
+            vec![
+                (json!(first_revision_id), json!(false), json!(["a"])),
+                (json!(revision_id), json!(false), json!(["b"])),
+                (json!(last_revision_id), json!(true), json!([])),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_filtered_rows_sorted() {
+        let app = AppState::from_config_file("config.json").expect("app creation failed");
+        let app = Arc::new(app);
+        let list = create_test_list(&app, "test_get_filtered_rows_sorted").await;
+        let incoming = string_rows(&["b", "10", "A", "9", "a"])
+            .chain([Ok(Row::from_cells(vec![None]))])
+            .collect::<Vec<_>>();
+        list.import_rows(incoming.into_iter(), TEST_USER_ID, None)
+            .await
+            .expect("import_rows");
+        let filter = RowFilter::default();
+        let sorted = |descending: bool, start: DbId, length: Option<DbId>| {
+            let sort = Some(RowSort {
+                column: 0,
+                descending,
+            });
+            let list = &list;
+            let filter = &filter;
+            async move {
+                let (rows, total) = list
+                    .get_filtered_rows_for_revision(list.revision_id, filter, &sort, start, length)
+                    .await
+                    .expect("get_filtered_rows_for_revision");
+                (row_values(&rows), total)
+            }
+        };
+
+        // Numbers by value, then text, then empty cells
+        let (values, total) = sorted(false, 0, None).await;
+        assert_eq!(values, vec!["9", "10", "A", "a", "b", "None"]);
+        assert_eq!(total, 6);
+        let (values, _) = sorted(true, 0, None).await;
+        assert_eq!(values, vec!["b", "A", "a", "10", "9", "None"]);
+        let (values, total) = sorted(false, 1, Some(2)).await;
+        assert_eq!(values, vec!["10", "A"]);
+        assert_eq!(total, 6);
+    }
+
+    #[tokio::test]
+    async fn test_failed_import_changes_nothing() {
+        let app = AppState::from_config_file("config.json").expect("app creation failed");
+        let app = Arc::new(app);
+        let list = create_test_list(&app, "test_failed_import_changes_nothing").await;
+        list.import_rows(string_rows(&["a", "b"]), TEST_USER_ID, None)
+            .await
+            .expect("import_rows");
+        let values: Vec<String> = (0..ROW_INSERT_BATCH_SIZE + 1)
+            .map(|num| format!("row {num}"))
+            .collect();
+        let values: Vec<&str> = values.iter().map(|s| s.as_str()).collect();
+
+        // A bad row after the first batch was written
+        let incoming = string_rows(&values).chain([Err("Bad row".into())]);
+        assert!(list
+            .import_rows(incoming, TEST_USER_ID, None)
+            .await
+            .is_err());
+        let rows = list.get_rows_for_revision(list.revision_id).await.unwrap();
+        assert_eq!(row_values(&rows), vec!["a", "b"]);
+
+        // A duplicate key after the first batch was written
+        let incoming = string_rows(&values).chain(string_rows(&["row 0"]));
+        assert!(list
+            .sync_rows(incoming, &[0], TEST_USER_ID, true, None)
+            .await
+            .is_err());
+        let rows = list.get_rows_for_revision(list.revision_id).await.unwrap();
+        assert_eq!(row_values(&rows), vec!["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn test_revert_to_revision() {
+        let app = AppState::from_config_file("config.json").expect("app creation failed");
+        let app = Arc::new(app);
+        let mut list = create_test_list(&app, "test_revert_to_revision").await;
+        let header_schema_id = list.header.schema.id;
+        list.import_rows(string_rows(&["a", "b", "c"]), TEST_USER_ID, None)
+            .await
+            .expect("import_rows");
+        let target_revision_id = list.revision_id;
+        let revision_id = list.snapshot().await.expect("snapshot");
+        assert!(list
+            .revert_to_revision(revision_id, TEST_USER_ID)
+            .await
+            .is_err());
+        assert!(list
+            .revert_to_revision(revision_id + 1, TEST_USER_ID)
+            .await
+            .is_err());
+
+        // Change row 1, delete row 2, add row 4, and switch the header schema
+        let mut conn = app.get_gulp_conn().await.unwrap();
+        let mut row = Row::from_db(&mut conn, list.id, 1, revision_id, &list.header)
+            .await
+            .expect("row 1");
+        row.cells = vec![Some(Cell::String("x".to_string()))];
+        row.revision_id = revision_id;
+        row.add_or_replace(&list.header, &mut conn, TEST_USER_ID)
+            .await
+            .expect("add_or_replace");
+        list.delete_row(2, TEST_USER_ID).await.expect("delete_row");
+        list.import_rows(string_rows(&["d"]), TEST_USER_ID, None)
+            .await
+            .expect("import_rows");
+        let hs = create_test_header_schema(&app, "test_revert_to_revision new").await;
+        list.set_header_schema(hs).await.expect("set_header_schema");
+        let rows = list.get_rows_for_revision(revision_id).await.unwrap();
+        assert_eq!(row_values(&rows), vec!["x", "c", "d"]);
+
+        let stats = list
+            .revert_to_revision(target_revision_id, TEST_USER_ID)
+            .await
+            .expect("revert_to_revision");
+        assert_eq!(
+            (stats.added, stats.changed, stats.unchanged, stats.removed),
+            (1, 1, 1, 1)
+        );
+        assert_eq!(list.revision_id, revision_id);
+        assert_eq!(list.header.schema.id, header_schema_id);
+        let header = Header::from_list_revision_id(&mut conn, list.id, revision_id)
+            .await
+            .expect("header");
+        assert_eq!(header.schema.id, header_schema_id);
+        let rows = list.get_rows_for_revision(revision_id).await.unwrap();
+        assert_eq!(row_values(&rows), vec!["a", "b", "c"]);
+
+        // The target revision itself is unchanged
+        let rows = list
+            .get_rows_for_revision(target_revision_id)
+            .await
+            .unwrap();
+        assert_eq!(row_values(&rows), vec!["a", "b", "c"]);
+    }
 }
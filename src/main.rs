@@ -16,14 +16,25 @@ pub mod data_source;
 pub mod data_source_as_file;
 pub mod data_source_line_converter;
 pub mod database_session_store;
+pub mod diff;
 pub mod error;
+pub mod export_ods;
+pub mod export_stream;
+pub mod export_xlsx;
+pub mod fetcher;
 pub mod file;
 pub mod gulp_response;
 pub mod header;
+pub mod job;
 pub mod list;
 pub mod oauth;
 pub mod row;
+pub mod row_filter;
+pub mod row_sort;
+pub mod scheduler;
+pub mod source_cache;
 pub mod user;
+pub mod wikidata;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -57,7 +68,7 @@ async fn main() -> Result<(), GulpError> {
             println!("{}", hs.generate_name());
             // let source = DataSource::from_db(&app,8).await.unwrap();
             // let list = list::List::from_id(&app, source.list_id).await.unwrap();
-            // list.update_from_source(&source, 1).await.unwrap();
+            // list.update_from_source(&source, 1, None).await.unwrap();
 
             // let session = app.store.load_session("yFE28eun2Mqag9y/g9+PqG2zeULtmLlCs3+C9ExmJiw=".to_string()).await;
             // let session = session.unwrap().unwrap();
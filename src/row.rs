@@ -4,6 +4,9 @@ use mysql_async::{prelude::*, Conn};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
+/// The `json` of a deleted row. Queries for the rows of a revision skip these.
+pub const TOMBSTONE_JSON: &str = "null";
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Row {
     pub id: DbId,
@@ -53,18 +56,34 @@ impl Row {
         }
     }
 
+    /// A tombstone, marking the row as deleted from the revision on
+    pub fn tombstone(list_id: DbId, row_num: DbId, revision_id: DbId, user_id: DbId) -> Self {
+        let mut ret = Self::new();
+        ret.list_id = list_id;
+        ret.row_num = row_num;
+        ret.revision_id = revision_id;
+        ret.json = TOMBSTONE_JSON.to_string();
+        ret.json_md5 = Self::md5(TOMBSTONE_JSON);
+        ret.user_id = user_id;
+        ret
+    }
+
     pub fn from_cells(cells: Vec<Option<Cell>>) -> Self {
         let mut ret = Self::new();
         ret.cells = cells;
         ret
     }
 
-    fn get_timestamp_from_row(v: &mysql_async::Value) -> String {
+    pub fn get_timestamp_from_row(v: &mysql_async::Value) -> String {
         v.as_sql(true).replace('\'', "")
     }
 
+    /// A row from the database; tombstones (deleted rows) have no cells, see `is_deleted`
     pub fn from_row(row: &mysql_async::Row, header: &Header) -> Option<Self> {
         let json: String = row.get(4)?;
+        if json == TOMBSTONE_JSON {
+            return Self::tombstone_from_row(row);
+        }
         let json: serde_json::Value = serde_json::from_str(&json).ok()?;
         let cells = json
             .as_array()?
@@ -85,6 +104,28 @@ impl Row {
         })
     }
 
+    fn tombstone_from_row(row: &mysql_async::Row) -> Option<Self> {
+        let json: String = row.get(4)?;
+        if json != TOMBSTONE_JSON {
+            return None;
+        }
+        Some(Self {
+            id: row.get(0)?,
+            list_id: row.get(1)?,
+            row_num: row.get(2)?,
+            revision_id: row.get(3)?,
+            json,
+            json_md5: row.get(5)?,
+            user_id: row.get(6)?,
+            modified: Self::get_timestamp_from_row(&row.get(7)?),
+            cells: vec![],
+        })
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.json == TOMBSTONE_JSON
+    }
+
     pub async fn row_exists_for_revision(
         conn: &mut Conn,
         list_id: DbId,
@@ -108,13 +149,53 @@ impl Row {
         )
     }
 
+    /// Fails if the header has key columns, and this row has an empty key,
+    /// or another row in the revision has the same key.
+    async fn check_unique_key(
+        &self,
+        header: &Header,
+        conn: &mut Conn,
+    ) -> Result<(), crate::GulpError> {
+        if header.schema.key_columns.is_empty() {
+            return Ok(());
+        }
+        let key_md5 = header
+            .schema
+            .get_key_md5(&self.cells)
+            .ok_or("Row has no value in a key column")?;
+        let sql = r#"SELECT row_num FROM `row`
+            WHERE list_id=:list_id AND key_md5=:key_md5 AND row_num!=:row_num
+            AND revision_id=(SELECT max(revision_id) FROM `row` i WHERE i.row_num = row.row_num AND i.list_id=:list_id AND revision_id<=:revision_id)
+            LIMIT 1"#;
+        let list_id = self.list_id;
+        let row_num = self.row_num;
+        let revision_id = self.revision_id;
+        let other_row_num = conn
+            .exec_iter(sql, params! {list_id,key_md5,row_num,revision_id})
+            .await?
+            .map_and_drop(mysql_async::from_row::<DbId>)
+            .await?;
+        match other_row_num.first() {
+            Some(other_row_num) => {
+                let key = header.schema.get_key(&self.cells).unwrap_or_default();
+                Err(format!(
+                    "Duplicate key '{}': already used by row #{other_row_num}",
+                    key.join("|")
+                )
+                .into())
+            }
+            None => Ok(()),
+        }
+    }
+
     pub async fn add_or_replace(
         &mut self,
         header: &Header,
         conn: &mut Conn,
         user_id: DbId,
     ) -> Result<(), crate::GulpError> {
-        let sql = r#"REPLACE INTO `row` (list_id,row_num,revision_id,json,json_md5,user_id) VALUES (:list_id,:row_num,:revision_id,:json,:json_md5,:user_id)"#;
+        self.check_unique_key(header, conn).await?;
+        let sql = r#"REPLACE INTO `row` (list_id,row_num,revision_id,json,json_md5,user_id,key_md5) VALUES (:list_id,:row_num,:revision_id,:json,:json_md5,:user_id,:key_md5)"#;
         let list_id = self.list_id;
         let row_num = self.row_num;
         let revision_id = self.revision_id;
@@ -122,16 +203,38 @@ impl Row {
         let json = self.as_json(header)["c"].to_owned();
         let json = serde_json::to_string(&json)?;
         let json_md5 = Self::md5(&json);
+        let key_md5 = header.schema.get_key_md5(&self.cells);
 
         conn.exec_drop(
             sql,
-            params! {list_id,row_num,revision_id,json,json_md5,user_id},
+            params! {list_id,row_num,revision_id,json,json_md5,user_id,key_md5},
         )
         .await?;
         self.id = conn.last_insert_id().ok_or("Row::add_or_replace")?;
         Ok(())
     }
 
+    /// Marks the row as deleted by writing a tombstone (JSON `null`) for `self.revision_id`.
+    /// Revisions before that one still show the previous version of the row.
+    pub async fn delete(&mut self, conn: &mut Conn, user_id: DbId) -> Result<(), crate::GulpError> {
+        let sql = r#"REPLACE INTO `row` (list_id,row_num,revision_id,json,json_md5,user_id) VALUES (:list_id,:row_num,:revision_id,:json,:json_md5,:user_id)"#;
+        let list_id = self.list_id;
+        let row_num = self.row_num;
+        let revision_id = self.revision_id;
+        let json = TOMBSTONE_JSON.to_string();
+        let json_md5 = Self::md5(&json);
+
+        conn.exec_drop(
+            sql,
+            params! {list_id,row_num,revision_id,json,json_md5,user_id},
+        )
+        .await?;
+        self.id = conn.last_insert_id().ok_or("Row::delete")?;
+        self.json = TOMBSTONE_JSON.to_string();
+        self.cells.clear();
+        Ok(())
+    }
+
     pub fn md5(s: &str) -> String {
         format!("{:x}", md5::compute(s))
     }
@@ -155,14 +258,16 @@ impl Row {
         json!(ret)
     }
 
+    /// The row number and one string per column of the header
     pub fn as_vec(&self, header: &Header) -> Vec<String> {
-        let mut ret: Vec<String> = self
-            .cells
+        let mut ret: Vec<String> = header
+            .schema
+            .columns
             .iter()
-            .zip(header.schema.columns.iter())
-            .map(|(cell, column)| match cell {
-                Some(c) => c.as_string(column),
-                None => String::new(),
+            .enumerate()
+            .map(|(num, column)| match self.cells.get(num) {
+                Some(Some(c)) => c.as_string(column),
+                _ => String::new(),
             })
             .collect();
         ret.insert(0, format!("{}", self.row_num));
@@ -0,0 +1,311 @@
+use crate::column::ColumnType;
+use crate::header::{Header, HeaderColumn};
+use crate::row::Row;
+use crate::GulpError;
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+const MAX_REGEX_SIZE: usize = 1 << 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterOperator {
+    Equals,
+    Contains,
+    Regex,
+    Empty,
+    NotEmpty,
+}
+
+/// A condition on a single column, as passed in the `filter` JSON array,
+/// e.g. `{"column":0,"op":"contains","value":"foo"}`
+#[derive(Clone, Debug, Deserialize)]
+pub struct ColumnFilter {
+    pub column: usize,
+    pub op: FilterOperator,
+    #[serde(default)]
+    pub value: String,
+    #[serde(skip)]
+    regex: Option<Regex>,
+}
+
+impl ColumnFilter {
+    /// The filter as an SQL condition on the stored row JSON, with the value of the
+    /// `param` parameter if it needs one. Only for columns stored as JSON strings, which
+    /// are the cell strings; other filters are checked with `matches`.
+    fn as_sql(&self, column: &HeaderColumn, param: &str) -> Option<(String, Option<String>)> {
+        if !matches!(
+            column.column_type,
+            ColumnType::String | ColumnType::WikidataEntity
+        ) {
+            return None;
+        }
+        let value = format!("JSON_EXTRACT(json,'$[{}]')", self.column);
+        let json_type = format!("IFNULL(JSON_TYPE({value}),'NULL')");
+        let empty =
+            format!("({json_type}='NULL' OR ({json_type}='STRING' AND JSON_UNQUOTE({value})=''))");
+        Some(match self.op {
+            FilterOperator::Equals if self.value.is_empty() => (empty, None),
+            FilterOperator::Equals => (
+                format!(
+                    "{json_type}='STRING' AND JSON_UNQUOTE({value}) COLLATE utf8mb4_bin=:{param}"
+                ),
+                Some(self.value.to_owned()),
+            ),
+            FilterOperator::Contains if self.value.is_empty() => ("TRUE".to_string(), None),
+            FilterOperator::Contains => {
+                let pattern = self
+                    .value
+                    .to_lowercase()
+                    .replace('!', "!!")
+                    .replace('%', "!%")
+                    .replace('_', "!_");
+                (
+                    format!("{json_type}='STRING' AND LOWER(JSON_UNQUOTE({value}) COLLATE utf8mb4_bin) LIKE :{param} ESCAPE '!'"),
+                    Some(format!("%{pattern}%")),
+                )
+            }
+            FilterOperator::Regex => return None,
+            FilterOperator::Empty => (empty, None),
+            FilterOperator::NotEmpty => (format!("NOT {empty}"), None),
+        })
+    }
+
+    fn matches(&self, row: &Row, column: &HeaderColumn) -> bool {
+        let value = match row.cells.get(self.column) {
+            Some(Some(cell)) => cell.as_string(column),
+            _ => String::new(),
+        };
+        match self.op {
+            FilterOperator::Equals => value == self.value,
+            FilterOperator::Contains => value.to_lowercase().contains(&self.value.to_lowercase()),
+            FilterOperator::Regex => self.regex.as_ref().is_some_and(|r| r.is_match(&value)),
+            FilterOperator::Empty => value.is_empty(),
+            FilterOperator::NotEmpty => !value.is_empty(),
+        }
+    }
+}
+
+/// Conditions for the `WHERE` clause of a row query, and their parameters
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SqlFilter {
+    pub conditions: Vec<String>,
+    pub params: Vec<(String, String)>,
+}
+
+impl SqlFilter {
+    /// The conditions, each preceded by `AND`
+    pub fn as_sql(&self) -> String {
+        self.conditions
+            .iter()
+            .map(|condition| format!(" AND ({condition})"))
+            .collect()
+    }
+}
+
+/// Column filters and a full-text search; a row has to match all of them
+#[derive(Clone, Debug, Default)]
+pub struct RowFilter {
+    pub columns: Vec<ColumnFilter>,
+    pub search: Option<String>,
+}
+
+impl RowFilter {
+    /// Reads the `filter` (JSON array of `ColumnFilter`) and `search` query parameters
+    pub fn from_params(
+        params: &HashMap<String, String>,
+        header: &Header,
+    ) -> Result<Self, GulpError> {
+        let mut columns: Vec<ColumnFilter> = match params.get("filter") {
+            Some(s) if !s.trim().is_empty() => serde_json::from_str(s)?,
+            _ => vec![],
+        };
+        for filter in columns.iter_mut() {
+            if filter.column >= header.schema.columns.len() {
+                return Err(format!("Filter: no column {}", filter.column).into());
+            }
+            if filter.op == FilterOperator::Regex {
+                let regex = RegexBuilder::new(&filter.value)
+                    .size_limit(MAX_REGEX_SIZE)
+                    .build()
+                    .map_err(|e| format!("Filter: bad regular expression: {e}"))?;
+                filter.regex = Some(regex);
+            }
+        }
+        let search = params
+            .get("search")
+            .map(|s| s.trim().to_lowercase())
+            .filter(|s| !s.is_empty());
+        Ok(Self { columns, search })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty() && self.search.is_none()
+    }
+
+    /// Splits the filter into the part MySQL can check, and the rest for `matches`
+    pub fn split_sql(&self, header: &Header) -> (SqlFilter, Self) {
+        let mut sql = SqlFilter::default();
+        let mut rest = Self {
+            columns: vec![],
+            search: self.search.to_owned(),
+        };
+        for filter in &self.columns {
+            let param = format!("filter_{}", sql.params.len());
+            let condition = header
+                .schema
+                .columns
+                .get(filter.column)
+                .and_then(|column| filter.as_sql(column, &param));
+            match condition {
+                Some((condition, value)) => {
+                    sql.conditions.push(condition);
+                    if let Some(value) = value {
+                        sql.params.push((param, value));
+                    }
+                }
+                None => rest.columns.push(filter.to_owned()),
+            }
+        }
+        (sql, rest)
+    }
+
+    pub fn matches(&self, row: &Row, header: &Header) -> bool {
+        let columns_match =
+            self.columns
+                .iter()
+                .all(|filter| match header.schema.columns.get(filter.column) {
+                    Some(column) => filter.matches(row, column),
+                    None => false,
+                });
+        if !columns_match {
+            return false;
+        }
+        match &self.search {
+            Some(search) => row
+                .cells
+                .iter()
+                .zip(header.schema.columns.iter())
+                .filter_map(|(cell, column)| Some(cell.as_ref()?.as_string(column)))
+                .any(|value| value.to_lowercase().contains(search)),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cell::Cell;
+    use crate::header::HeaderSchema;
+
+    fn header() -> Header {
+        let mut header = Header::new();
+        header.schema = HeaderSchema::from_name_json(
+            "Test",
+            r#"{"columns":[{"column_type":"String"},{"column_type":"String"}]}"#,
+        )
+        .unwrap();
+        header
+    }
+
+    fn row(a: &str, b: Option<&str>) -> Row {
+        Row::from_cells(vec![
+            Some(Cell::String(a.to_string())),
+            b.map(|b| Cell::String(b.to_string())),
+        ])
+    }
+
+    fn filter(params: &[(&str, &str)]) -> RowFilter {
+        let params: HashMap<String, String> = params
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        RowFilter::from_params(&params, &header()).unwrap()
+    }
+
+    #[test]
+    fn test_matches() {
+        let header = header();
+        let rows = [
+            row("Foo", Some("bar")),
+            row("baz", None),
+            row("foo", Some("Qux")),
+        ];
+        let count = |f: &RowFilter| rows.iter().filter(|r| f.matches(r, &header)).count();
+        let count_filter = |json: &str| count(&filter(&[("filter", json)]));
+
+        assert!(filter(&[]).is_empty());
+        assert_eq!(
+            count_filter(r#"[{"column":0,"op":"equals","value":"foo"}]"#),
+            1
+        );
+        assert_eq!(
+            count_filter(r#"[{"column":0,"op":"contains","value":"FO"}]"#),
+            2
+        );
+        assert_eq!(
+            count_filter(r#"[{"column":0,"op":"regex","value":"^ba"}]"#),
+            1
+        );
+        assert_eq!(count_filter(r#"[{"column":1,"op":"empty"}]"#), 1);
+        assert_eq!(count_filter(r#"[{"column":1,"op":"notempty"}]"#), 2);
+        assert_eq!(count(&filter(&[("search", "qux")])), 1);
+        let json = r#"[{"column":0,"op":"contains","value":"foo"}]"#;
+        assert_eq!(count(&filter(&[("filter", json), ("search", "BAR")])), 1);
+
+        let json = r#"[{"column":2,"op":"empty"}]"#;
+        let params = HashMap::from([("filter".to_string(), json.to_string())]);
+        assert!(RowFilter::from_params(&params, &header).is_err());
+    }
+
+    #[test]
+    fn test_split_sql() {
+        let mut header = header();
+        header.schema = HeaderSchema::from_name_json(
+            "Test",
+            r#"{"columns":[{"column_type":"String"},{"column_type":"Number"}]}"#,
+        )
+        .unwrap();
+        let json = r#"[
+            {"column":0,"op":"equals","value":"a'b"},
+            {"column":0,"op":"contains","value":"50%_Off!"},
+            {"column":0,"op":"regex","value":"^a"},
+            {"column":0,"op":"notempty"},
+            {"column":1,"op":"equals","value":"5"}
+        ]"#;
+        let params = HashMap::from([
+            ("filter".to_string(), json.to_string()),
+            ("search".to_string(), "x".to_string()),
+        ]);
+        let filter = RowFilter::from_params(&params, &header).unwrap();
+        let (sql, rest) = filter.split_sql(&header);
+
+        // Values are passed as parameters
+        assert_eq!(
+            sql.params,
+            vec![
+                ("filter_0".to_string(), "a'b".to_string()),
+                ("filter_1".to_string(), "%50!%!_off!!%".to_string()),
+            ]
+        );
+        assert_eq!(sql.conditions.len(), 3);
+        assert!(sql.conditions[0]
+            .ends_with("JSON_UNQUOTE(JSON_EXTRACT(json,'$[0]')) COLLATE utf8mb4_bin=:filter_0"));
+        assert!(sql.conditions[1].contains("LIKE :filter_1 ESCAPE '!'"));
+        assert!(sql.conditions[2]
+            .starts_with("NOT (IFNULL(JSON_TYPE(JSON_EXTRACT(json,'$[0]')),'NULL')='NULL'"));
+        assert!(sql.as_sql().starts_with(" AND (IFNULL("));
+        assert_eq!(sql.as_sql().matches(" AND (").count(), 3);
+
+        // Regular expressions, non-string columns, and the search are left to `matches`
+        let ops: Vec<_> = rest.columns.iter().map(|f| (f.column, f.op)).collect();
+        assert_eq!(
+            ops,
+            vec![(0, FilterOperator::Regex), (1, FilterOperator::Equals)]
+        );
+        assert_eq!(rest.search.as_deref(), Some("x"));
+        assert!(RowFilter::default().split_sql(&header).1.is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+use crate::column::ColumnType;
+use crate::header::Header;
+use crate::GulpError;
+use std::collections::HashMap;
+
+/// Sorts rows by the values in one column; rows with an empty cell in that column
+/// always come last. Ties keep their original (`row_num`) order.
+#[derive(Clone, Debug)]
+pub struct RowSort {
+    pub column: usize,
+    pub descending: bool,
+}
+
+impl RowSort {
+    /// Reads the `sort` (column index) and `dir` (`asc` or `desc`) query parameters
+    pub fn from_params(
+        params: &HashMap<String, String>,
+        header: &Header,
+    ) -> Result<Option<Self>, GulpError> {
+        let column = match params.get("sort") {
+            Some(s) if !s.trim().is_empty() => s.trim().parse::<usize>()?,
+            _ => return Ok(None),
+        };
+        if column >= header.schema.columns.len() {
+            return Err(format!("Sort: no column {column}").into());
+        }
+        let descending = match params.get("dir").map(|s| s.as_str()) {
+            None | Some("asc") => false,
+            Some("desc") => true,
+            Some(other) => return Err(format!("Sort: bad direction '{other}'").into()),
+        };
+        Ok(Some(Self { column, descending }))
+    }
+
+    /// An `ORDER BY` expression for row queries, ordering on the stored row JSON.
+    /// Strings that are numbers come before other text, which is compared lowercased
+    /// by code point (`utf8mb4_bin`).
+    pub fn as_sql(&self, header: &Header) -> String {
+        let value = format!("JSON_EXTRACT(json,'$[{}]')", self.column);
+        let json_type = format!("IFNULL(JSON_TYPE({value}),'NULL')");
+        let string = format!("JSON_UNQUOTE({value})");
+        let text = |s: &str| format!("LOWER({s} COLLATE utf8mb4_bin)");
+        let column_type = header
+            .schema
+            .columns
+            .get(self.column)
+            .map(|column| column.column_type.to_owned())
+            .unwrap_or(ColumnType::String);
+        let keys = match column_type {
+            ColumnType::String => {
+                // Strings that are numbers come first, by value
+                let is_number = format!(
+                    "{string} REGEXP '^[[:space:]]*[-+]?([0-9]+[.]?[0-9]*|[.][0-9]+)([eE][-+]?[0-9]+)?[[:space:]]*$'"
+                );
+                vec![
+                    format!("NOT ({is_number})"),
+                    format!("IF({is_number},{string}+0,NULL)"),
+                    format!("IF({is_number},NULL,{})", text(&string)),
+                ]
+            }
+            ColumnType::WikiPage => {
+                let title = format!(
+                    "IF({json_type}='OBJECT',JSON_UNQUOTE(JSON_EXTRACT({value},'$.title')),{string})"
+                );
+                vec![format!("REPLACE({},'_',' ')", text(&title))]
+            }
+            ColumnType::Location => vec![
+                format!("JSON_EXTRACT({value},'$.lat')+0"),
+                format!("JSON_EXTRACT({value},'$.lon')+0"),
+            ],
+            ColumnType::Number => {
+                vec![format!(
+                    "IF({json_type}='OBJECT',JSON_EXTRACT({value},'$.value'),{value})+0"
+                )]
+            }
+            ColumnType::Date => {
+                // "+2001-12-31T00:00:00Z" => 2001,"-12-31T00:00:00Z"
+                let time =
+                    format!("TRIM(LEADING '+' FROM JSON_UNQUOTE(JSON_EXTRACT({value},'$.time')))");
+                vec![
+                    format!("IF(LEFT({time},1)='-',-SUBSTRING_INDEX(SUBSTRING({time},2),'-',1),SUBSTRING_INDEX({time},'-',1))+0"),
+                    format!("SUBSTRING({time},LOCATE('-',{time},2)) COLLATE utf8mb4_bin"),
+                ]
+            }
+            ColumnType::Boolean => vec![format!("{string}='true'")],
+            ColumnType::WikidataEntity => {
+                // IDs like Q123 come first, by letter and number
+                let is_entity = format!("{string} REGEXP '^.[0-9]+$'");
+                vec![
+                    format!("NOT ({is_entity})"),
+                    format!("IF({is_entity},LEFT({string},1) COLLATE utf8mb4_bin,NULL)"),
+                    format!("IF({is_entity},SUBSTRING({string},2)+0,NULL)"),
+                    format!("IF({is_entity},NULL,{})", text(&string)),
+                ]
+            }
+        };
+        let direction = if self.descending { " DESC" } else { "" };
+        // Empty cells last in either direction, ties by row number
+        let mut order_by = vec![format!("{json_type}='NULL'")];
+        order_by.extend(keys.into_iter().map(|key| format!("{key}{direction}")));
+        order_by.push("row_num".to_string());
+        order_by.join(",")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_as_sql() {
+        let mut header = Header::new();
+        header.schema = crate::header::HeaderSchema::from_name_json(
+            "Test",
+            r#"{"columns":[{"column_type":"String"},{"column_type":"Number"}]}"#,
+        )
+        .unwrap();
+        let sort = RowSort {
+            column: 1,
+            descending: true,
+        };
+        assert_eq!(
+            sort.as_sql(&header),
+            "IFNULL(JSON_TYPE(JSON_EXTRACT(json,'$[1]')),'NULL')='NULL',\
+            IF(IFNULL(JSON_TYPE(JSON_EXTRACT(json,'$[1]')),'NULL')='OBJECT',\
+            JSON_EXTRACT(JSON_EXTRACT(json,'$[1]'),'$.value'),JSON_EXTRACT(json,'$[1]'))+0 DESC,\
+            row_num"
+        );
+        let sort = RowSort {
+            column: 0,
+            descending: false,
+        };
+        let sql = sort.as_sql(&header);
+        let keys: Vec<_> = sql.split(",IF(").collect();
+        assert_eq!(keys.len(), 3);
+        assert!(keys[0].contains(",NOT (JSON_UNQUOTE(JSON_EXTRACT(json,'$[0]')) REGEXP "));
+        assert!(keys[2].ends_with(
+            "LOWER(JSON_UNQUOTE(JSON_EXTRACT(json,'$[0]')) COLLATE utf8mb4_bin)),row_num"
+        ));
+        assert!(!sql.contains("DESC"));
+    }
+}
@@ -0,0 +1,161 @@
+use crate::app_state::AppState;
+use crate::data_source::DataSource;
+use crate::job::Job;
+use crate::GulpError;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// How often the scheduler checks for sources that are due for a refresh
+const SCHEDULER_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Shortest allowed refresh interval, in seconds
+const MIN_REFRESH_INTERVAL: i64 = 15 * 60;
+
+/// Number of upcoming runs of a cron schedule that are checked for `MIN_REFRESH_INTERVAL`
+const CRON_CHECKED_RUNS: usize = 100;
+
+/// When a data source is refreshed automatically. Either an interval like "30m", "6h", "1d",
+/// or a cron expression like "0 3 * * *" (minute hour day month weekday, in UTC).
+/// Neither may run more often than every `MIN_REFRESH_INTERVAL` seconds.
+#[derive(Clone, Debug)]
+pub enum RefreshSchedule {
+    Interval(Duration),
+    Cron(Box<cron::Schedule>),
+}
+
+impl RefreshSchedule {
+    pub fn new(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if s.split_whitespace().count() > 1 {
+            // The cron crate wants seconds as the first field
+            let s = match s.split_whitespace().count() {
+                5 => format!("0 {s}"),
+                _ => s.to_string(),
+            };
+            let schedule = cron::Schedule::from_str(&s).ok()?;
+            let runs: Vec<_> = schedule.upcoming(Utc).take(CRON_CHECKED_RUNS).collect();
+            let too_often = runs
+                .windows(2)
+                .any(|pair| (pair[1] - pair[0]).num_seconds() < MIN_REFRESH_INTERVAL);
+            if too_often {
+                return None;
+            }
+            return Some(Self::Cron(Box::new(schedule)));
+        }
+        let unit_pos = s.find(|c: char| !c.is_ascii_digit())?;
+        let number: i64 = s[..unit_pos].parse().ok()?;
+        let unit_seconds = match &s[unit_pos..] {
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 60 * 60 * 24,
+            "w" => 60 * 60 * 24 * 7,
+            _ => return None,
+        };
+        let seconds = number.checked_mul(unit_seconds)?;
+        if seconds < MIN_REFRESH_INTERVAL {
+            return None;
+        }
+        Some(Self::Interval(Duration::try_seconds(seconds)?))
+    }
+
+    /// The time of the first refresh after `last_run`
+    pub fn next_after(&self, last_run: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Interval(interval) => Some(*last_run + *interval),
+            Self::Cron(schedule) => schedule.after(last_run).next(),
+        }
+    }
+
+    /// A source that was never refreshed by the scheduler is due right away
+    fn is_due(&self, last_run: Option<DateTime<Utc>>, now: &DateTime<Utc>) -> bool {
+        match last_run {
+            Some(last_run) => match self.next_after(&last_run) {
+                Some(next_run) => next_run <= *now,
+                None => false,
+            },
+            None => true,
+        }
+    }
+}
+
+/// Queues update jobs for data sources whose refresh schedule is due, on behalf of their owners
+pub async fn run_scheduler(app: Arc<AppState>) {
+    loop {
+        if let Err(e) = queue_due_sources(&app).await {
+            tracing::error!("Could not queue scheduled source updates: {e}");
+        }
+        tokio::time::sleep(SCHEDULER_INTERVAL).await;
+    }
+}
+
+async fn queue_due_sources(app: &Arc<AppState>) -> Result<(), GulpError> {
+    let now = Utc::now();
+    for source in DataSource::get_scheduled(app).await? {
+        let schedule = match source
+            .refresh_schedule
+            .as_deref()
+            .and_then(RefreshSchedule::new)
+        {
+            Some(schedule) => schedule,
+            None => continue,
+        };
+        if Job::has_unfinished(app, source.id).await? {
+            continue;
+        }
+        let last_run = Job::last_scheduled_run(app, source.id)
+            .await?
+            .and_then(|timestamp| Utc.timestamp_opt(timestamp, 0).single());
+        if schedule.is_due(last_run, &now) {
+            Job::create(app, &source, source.user_id, true).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refresh_schedule() {
+        let last_run = Utc.with_ymd_and_hms(2024, 5, 1, 12, 30, 0).unwrap();
+        let schedule = RefreshSchedule::new("6h").unwrap();
+        assert_eq!(
+            schedule.next_after(&last_run),
+            Utc.with_ymd_and_hms(2024, 5, 1, 18, 30, 0).single()
+        );
+        let schedule = RefreshSchedule::new("0 3 * * *").unwrap();
+        assert_eq!(
+            schedule.next_after(&last_run),
+            Utc.with_ymd_and_hms(2024, 5, 2, 3, 0, 0).single()
+        );
+        assert!(schedule.is_due(None, &last_run));
+        assert!(!schedule.is_due(
+            Some(last_run),
+            &(last_run + Duration::try_hours(1).unwrap())
+        ));
+        assert!(schedule.is_due(Some(last_run), &(last_run + Duration::try_days(1).unwrap())));
+
+        assert!(RefreshSchedule::new("5m").is_none());
+        assert!(RefreshSchedule::new("1y").is_none());
+        assert!(RefreshSchedule::new("h").is_none());
+        assert!(RefreshSchedule::new("0 3 * *").is_none());
+    }
+
+    #[test]
+    fn test_refresh_schedule_limits() {
+        // Cron schedules that run more often than the minimum interval
+        assert!(RefreshSchedule::new("* * * * *").is_none());
+        assert!(RefreshSchedule::new("*/10 * * * *").is_none());
+        assert!(RefreshSchedule::new("0,5 3 * * *").is_none());
+        assert!(RefreshSchedule::new("*/15 * * * *").is_some());
+        assert!(RefreshSchedule::new("0 3,15 * * *").is_some());
+
+        // Intervals that do not fit into seconds
+        assert!(RefreshSchedule::new("9223372036854775807w").is_none());
+        assert!(RefreshSchedule::new("99999999999999999d").is_none());
+        assert!(RefreshSchedule::new("99999999999999999999m").is_none());
+        assert!(RefreshSchedule::new("52w").is_some());
+    }
+}
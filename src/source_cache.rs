@@ -0,0 +1,301 @@
+use crate::app_state::AppState;
+use crate::fetcher::{Fetcher, HttpValidators, UrlFetch};
+use crate::header::DbId;
+use crate::GulpError;
+use chrono::Utc;
+use mysql_async::prelude::*;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The last download of a URL, PagePile or SPARQL data source. The file is kept on disk, with
+/// the validators for conditional requests, and the MD5 of the content last imported.
+/// It only applies to the URL it was downloaded from.
+#[derive(Clone, Debug)]
+pub struct SourceCache {
+    pub source_id: DbId,
+    pub url: String,
+    pub path: String,
+    pub validators: HttpValidators,
+    pub content_md5: String,
+    pub imported_md5: Option<String>,
+    pub checked: i64, // Unix timestamp of the last request to the server
+}
+
+impl SourceCache {
+    async fn from_db(app: &Arc<AppState>, source_id: DbId) -> Result<Option<Self>, GulpError> {
+        let sql = r#"SELECT source_id,url,path,etag,last_modified,content_md5,imported_md5,UNIX_TIMESTAMP(checked) FROM `data_source_cache` WHERE source_id=:source_id"#;
+        let caches = app
+            .get_gulp_conn()
+            .await?
+            .exec_iter(sql, params! {source_id})
+            .await?
+            .map_and_drop(|row| Self::from_row(&row))
+            .await?;
+        Ok(caches.into_iter().flatten().next())
+    }
+
+    fn from_row(row: &mysql_async::Row) -> Option<Self> {
+        Some(Self {
+            source_id: row.get(0)?,
+            url: row.get(1)?,
+            path: row.get(2)?,
+            validators: HttpValidators {
+                etag: row.get(3)?,
+                last_modified: row.get(4)?,
+            },
+            content_md5: row.get(5)?,
+            imported_md5: row.get(6)?,
+            checked: row.get(7)?,
+        })
+    }
+
+    async fn save(&self, app: &Arc<AppState>) -> Result<(), GulpError> {
+        let source_id = self.source_id;
+        let url = self.url.to_owned();
+        let path = self.path.to_owned();
+        let etag = self.validators.etag.to_owned();
+        let last_modified = self.validators.last_modified.to_owned();
+        let content_md5 = self.content_md5.to_owned();
+        let imported_md5 = self.imported_md5.to_owned();
+        let checked = self.checked;
+        let sql = "REPLACE INTO `data_source_cache` (source_id,url,path,etag,last_modified,content_md5,imported_md5,checked) VALUES (:source_id,:url,:path,:etag,:last_modified,:content_md5,:imported_md5,FROM_UNIXTIME(:checked))";
+        app.get_gulp_conn()
+            .await?
+            .exec_drop(
+                sql,
+                params! {source_id,url,path,etag,last_modified,content_md5,imported_md5,checked},
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Returns the cached download of `url`, asking the server only for changed content.
+    /// With `max_age` (seconds), a cache checked more recently than that is used as it is.
+    /// `accept` is the media type to ask the server for, if any.
+    pub async fn fetch(
+        app: &Arc<AppState>,
+        source_id: DbId,
+        url: &str,
+        accept: Option<&str>,
+        max_age: Option<i64>,
+    ) -> Result<Self, GulpError> {
+        let now = Utc::now().timestamp();
+        let cache = Self::from_db(app, source_id)
+            .await?
+            .filter(|cache| cache.is_for(url));
+        if let Some(cache) = &cache {
+            if cache.is_fresh(max_age, now) {
+                return Ok(cache.to_owned());
+            }
+        }
+        let path = PathBuf::from(app.get_source_cache_filename(source_id));
+        let cache = Self::download(&app.fetcher, cache, source_id, url, accept, &path, now).await?;
+        cache.save(app).await?;
+        Ok(cache)
+    }
+
+    /// Whether this is a download of `url` that is still on disk
+    fn is_for(&self, url: &str) -> bool {
+        self.url == url && Path::new(&self.path).exists()
+    }
+
+    /// Whether this was checked at most `max_age` seconds before `now`
+    fn is_fresh(&self, max_age: Option<i64>, now: i64) -> bool {
+        max_age.is_some_and(|max_age| now - self.checked <= max_age)
+    }
+
+    /// Downloads `url` to `path`, unless the server reports that `cache` is still current.
+    /// Returns the new state of the cache, checked at `now`.
+    async fn download(
+        fetcher: &Fetcher,
+        cache: Option<Self>,
+        source_id: DbId,
+        url: &str,
+        accept: Option<&str>,
+        path: &Path,
+        now: i64,
+    ) -> Result<Self, GulpError> {
+        let dir = path
+            .parent()
+            .ok_or("No directory for source cache")?
+            .to_owned();
+        let validators = cache
+            .as_ref()
+            .map(|cache| cache.validators.to_owned())
+            .unwrap_or_default();
+        let fetched = fetcher
+            .fetch_to_file(url, accept, &validators, &dir)
+            .await?;
+
+        let mut cache = match (fetched, cache) {
+            (UrlFetch::NotModified, Some(cache)) => cache,
+            (UrlFetch::NotModified, None) => {
+                return Err("Server reports no change, but there is no cached file".into())
+            }
+            (
+                UrlFetch::Downloaded {
+                    file,
+                    content_md5,
+                    validators,
+                },
+                cache,
+            ) => {
+                file.persist(path).map_err(|e| e.error)?;
+                Self {
+                    source_id,
+                    url: url.to_string(),
+                    path: path.to_string_lossy().to_string(),
+                    validators,
+                    content_md5,
+                    imported_md5: cache.and_then(|cache| cache.imported_md5),
+                    checked: now,
+                }
+            }
+        };
+        cache.checked = now;
+        Ok(cache)
+    }
+
+    pub fn open(&self) -> Result<File, GulpError> {
+        Ok(File::open(&self.path)?)
+    }
+
+    /// Whether this content was imported before, so the list has it already
+    pub fn is_imported(&self) -> bool {
+        self.imported_md5.as_ref() == Some(&self.content_md5)
+    }
+
+    /// Records that this content was imported. Only changes the database, if the cache
+    /// was not replaced by a newer download in the meantime.
+    pub async fn set_imported(&self, app: &Arc<AppState>) -> Result<(), GulpError> {
+        let source_id = self.source_id;
+        let content_md5 = self.content_md5.to_owned();
+        let sql = "UPDATE `data_source_cache` SET imported_md5=:content_md5 WHERE source_id=:source_id AND content_md5=:content_md5";
+        app.get_gulp_conn()
+            .await?
+            .exec_drop(sql, params! {source_id,content_md5})
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetcher::FetcherConfig;
+    use axum::http::{header, HeaderMap, StatusCode};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tempfile::tempdir;
+
+    const CONTENT: &str = "a,b\n";
+    const ETAG: &str = "\"v1\"";
+    static NOT_MODIFIED: AtomicUsize = AtomicUsize::new(0);
+
+    /// Serves `CONTENT`, or "not modified" for a request with its ETag
+    async fn endpoint(headers: HeaderMap) -> (StatusCode, HeaderMap, &'static str) {
+        let mut response_headers = HeaderMap::new();
+        response_headers.insert(header::ETAG, ETAG.parse().unwrap());
+        let etag = headers
+            .get(header::IF_NONE_MATCH)
+            .and_then(|v| v.to_str().ok());
+        if etag == Some(ETAG) {
+            NOT_MODIFIED.fetch_add(1, Ordering::SeqCst);
+            return (StatusCode::NOT_MODIFIED, response_headers, "");
+        }
+        (StatusCode::OK, response_headers, CONTENT)
+    }
+
+    #[tokio::test]
+    async fn test_download() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let router = axum::Router::new()
+            .route("/a.csv", axum::routing::get(endpoint))
+            .route("/b.csv", axum::routing::get(endpoint));
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(router.into_make_service());
+        tokio::spawn(server);
+        let config = FetcherConfig::from_config(&json!({"allow_private_addresses":true}));
+        let fetcher = Fetcher::new(config).unwrap();
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("1");
+        let url = format!("http://{address}/a.csv");
+
+        // First download
+        let mut cache = SourceCache::download(&fetcher, None, 1, &url, None, &path, 100)
+            .await
+            .unwrap();
+        assert_eq!(cache.url, url);
+        assert_eq!(cache.content_md5, format!("{:x}", md5::compute(CONTENT)));
+        assert_eq!(cache.validators.etag.as_deref(), Some(ETAG));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), CONTENT);
+        assert!(!cache.is_imported());
+        assert!(cache.is_for(&url));
+        assert!(cache.is_fresh(Some(60), 160));
+        assert!(!cache.is_fresh(Some(60), 161));
+        assert!(!cache.is_fresh(None, 100));
+
+        // Unchanged content is not downloaded, and known to be imported
+        cache.imported_md5 = Some(cache.content_md5.to_owned());
+        let not_modified = NOT_MODIFIED.load(Ordering::SeqCst);
+        let cache = SourceCache::download(&fetcher, Some(cache), 1, &url, None, &path, 200)
+            .await
+            .unwrap();
+        assert_eq!(NOT_MODIFIED.load(Ordering::SeqCst), not_modified + 1);
+        assert_eq!(cache.checked, 200);
+        assert!(cache.is_imported());
+
+        // The cache does not apply to another URL, which is downloaded in full
+        let url = format!("http://{address}/b.csv");
+        assert!(!cache.is_for(&url));
+        let cache = SourceCache::download(&fetcher, None, 1, &url, None, &path, 300)
+            .await
+            .unwrap();
+        assert_eq!(NOT_MODIFIED.load(Ordering::SeqCst), not_modified + 1);
+        assert_eq!(cache.url, url);
+        assert!(!cache.is_imported());
+    }
+
+    #[tokio::test]
+    async fn test_set_imported() {
+        let app = AppState::from_config_file("config.json").expect("app creation failed");
+        let app = Arc::new(app);
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("1");
+        std::fs::write(&path, CONTENT).unwrap();
+        let mut cache = SourceCache {
+            source_id: Utc::now().timestamp_nanos_opt().unwrap_or_default() as DbId % 1_000_000_000,
+            url: "https://example.org/a.csv".to_string(),
+            path: path.to_string_lossy().to_string(),
+            validators: HttpValidators::default(),
+            content_md5: "old".to_string(),
+            imported_md5: None,
+            checked: 100,
+        };
+        cache.save(&app).await.unwrap();
+        let old = cache.clone();
+        // A newer download replaced the cache
+        cache.content_md5 = "new".to_string();
+        cache.save(&app).await.unwrap();
+
+        old.set_imported(&app).await.unwrap();
+        let from_db = SourceCache::from_db(&app, cache.source_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(from_db.imported_md5, None);
+        assert!(!from_db.is_imported());
+
+        cache.set_imported(&app).await.unwrap();
+        let from_db = SourceCache::from_db(&app, cache.source_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(from_db.imported_md5.as_deref(), Some("new"));
+        assert!(from_db.is_imported());
+        assert!(from_db.is_for("https://example.org/a.csv"));
+    }
+}
@@ -211,4 +211,14 @@ impl User {
         let access = self.get_access_for_list(list_id).await;
         access.contains("admin") || access.contains("write") || access.contains("edit_row")
     }
+
+    pub async fn can_delete_row(&self, list_id: DbId) -> bool {
+        let access = self.get_access_for_list(list_id).await;
+        access.contains("admin") || access.contains("write") || access.contains("delete_row")
+    }
+
+    pub async fn can_revert_list(&self, list_id: DbId) -> bool {
+        let access = self.get_access_for_list(list_id).await;
+        access.contains("admin")
+    }
 }
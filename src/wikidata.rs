@@ -0,0 +1,211 @@
+use crate::GulpError;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub const WIKIDATA_API: &str = "https://www.wikidata.org/w/api.php";
+const ENTITIES_PER_REQUEST: usize = 50; // wbgetentities limit
+const MAX_CACHE_SIZE: usize = 100_000;
+
+type CacheKey = (String, String); // (language, entity ID)
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct EntityLabel {
+    pub label: Option<String>,
+    pub description: Option<String>,
+    /// The entity does not exist on Wikidata, or can not be looked up there (MediaInfo)
+    pub missing: bool,
+}
+
+impl EntityLabel {
+    fn missing() -> Self {
+        Self {
+            missing: true,
+            ..Default::default()
+        }
+    }
+
+    fn from_entity(entity: &Value, language: &str) -> Self {
+        // Lexemes have lemmas instead of labels
+        let label = entity
+            .get("labels")
+            .or_else(|| entity.get("lemmas"))
+            .and_then(|labels| Self::term_for_language(labels, language));
+        let description = entity
+            .get("descriptions")
+            .and_then(|descriptions| Self::term_for_language(descriptions, language));
+        Self {
+            label,
+            description,
+            missing: false,
+        }
+    }
+
+    fn term_for_language(terms: &Value, language: &str) -> Option<String> {
+        let term = match terms.get(language) {
+            Some(term) => term,
+            None => terms.as_object()?.values().next()?,
+        };
+        Some(term.get("value")?.as_str()?.to_string())
+    }
+}
+
+/// Looks up labels and descriptions of Wikidata entities via `wbgetentities`.
+/// Results are cached per language, including entities that are missing;
+/// the API URL can point to a local stand-in.
+#[derive(Clone, Debug)]
+pub struct WikidataLabels {
+    api_url: String,
+    cache: Arc<RwLock<HashMap<CacheKey, EntityLabel>>>,
+}
+
+impl WikidataLabels {
+    pub fn new(api_url: &str) -> Self {
+        Self {
+            api_url: api_url.to_string(),
+            cache: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    pub async fn get(
+        &self,
+        ids: &[String],
+        language: &str,
+    ) -> Result<HashMap<String, EntityLabel>, GulpError> {
+        let mut ret = HashMap::new();
+        let mut missing = vec![];
+        {
+            let cache = self.cache.read().await;
+            for id in ids {
+                match cache.get(&(language.to_string(), id.to_owned())) {
+                    Some(label) => {
+                        ret.insert(id.to_owned(), label.to_owned());
+                    }
+                    None => missing.push(id.to_owned()),
+                }
+            }
+        }
+        missing.sort();
+        missing.dedup();
+        // MediaInfo entities live on Commons, not Wikidata
+        let (mediainfo, missing): (Vec<_>, Vec<_>) =
+            missing.into_iter().partition(|id| id.starts_with('M'));
+        for id in mediainfo {
+            ret.insert(id, EntityLabel::missing());
+        }
+        if missing.is_empty() {
+            return Ok(ret);
+        }
+
+        let client = crate::list::List::get_client()?;
+        let futures: Vec<_> = missing
+            .chunks(ENTITIES_PER_REQUEST)
+            .map(|chunk| self.load_chunk(&client, chunk, language))
+            .collect();
+        let mut new_labels = HashMap::new();
+        for result in futures::future::join_all(futures).await {
+            new_labels.extend(result?);
+        }
+
+        let mut cache = self.cache.write().await;
+        if cache.len() + new_labels.len() > MAX_CACHE_SIZE {
+            cache.clear();
+        }
+        for (id, label) in new_labels {
+            cache.insert((language.to_string(), id.to_owned()), label.to_owned());
+            ret.insert(id, label);
+        }
+        Ok(ret)
+    }
+
+    async fn load_chunk(
+        &self,
+        client: &reqwest::Client,
+        ids: &[String],
+        language: &str,
+    ) -> Result<HashMap<String, EntityLabel>, GulpError> {
+        let ids = ids.join("|");
+        let params = [
+            ("action", "wbgetentities"),
+            ("format", "json"),
+            ("props", "labels|descriptions"),
+            ("languagefallback", "1"),
+            ("ids", ids.as_str()),
+            ("languages", language),
+        ];
+        let json: Value = client
+            .get(&self.api_url)
+            .query(&params)
+            .send()
+            .await?
+            .json()
+            .await?;
+        let entities = json
+            .get("entities")
+            .and_then(|entities| entities.as_object())
+            .ok_or("wbgetentities: no entities in response")?;
+        // Missing entities are kept as well, so they are not requested again
+        Ok(ids
+            .split('|')
+            .map(|id| match entities.get(id) {
+                Some(entity) if entity.get("missing").is_none() => {
+                    (id.to_string(), EntityLabel::from_entity(entity, language))
+                }
+                _ => (id.to_string(), EntityLabel::missing()),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::Query, routing::get, Json, Router};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn test_get_labels_from_local_api() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls2 = calls.clone();
+        let app = Router::new().route(
+            "/w/api.php",
+            get(move |Query(params): Query<HashMap<String, String>>| async move {
+                calls2.fetch_add(1, Ordering::SeqCst);
+                assert_eq!(params["languages"], "de");
+                let entities: HashMap<_, _> = params["ids"]
+                    .split('|')
+                    .map(|id| match id {
+                        "Q42" => (id, json!({"labels":{"de":{"language":"de","value":"Douglas Adams"}},"descriptions":{}})),
+                        _ => (id, json!({"missing":""})),
+                    })
+                    .collect();
+                Json(json!({ "entities": entities }))
+            }),
+        );
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+
+        let labels = WikidataLabels::new(&format!("http://{addr}/w/api.php"));
+        let ids = vec!["Q42".to_string(), "Q0".to_string(), "M123".to_string()];
+        let result = labels.get(&ids, "de").await.unwrap();
+        assert_eq!(result["Q42"].label, Some("Douglas Adams".to_string()));
+        assert_eq!(result["Q42"].description, None);
+        assert!(!result["Q42"].missing);
+        assert!(result["Q0"].missing);
+        assert!(result["M123"].missing);
+
+        // Cached, including the missing entity
+        let result = labels.get(&ids, "de").await.unwrap();
+        assert_eq!(result["Q42"].label, Some("Douglas Adams".to_string()));
+        assert!(result["Q0"].missing);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}